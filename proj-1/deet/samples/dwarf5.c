@@ -0,0 +1,15 @@
+#include <stdio.h>
+
+int counter = 0;
+
+int increment(int amount) {
+    counter += amount;
+    return counter;
+}
+
+int main() {
+    increment(1);
+    increment(2);
+    printf("%d\n", counter);
+    return 0;
+}
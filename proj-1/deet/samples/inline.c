@@ -0,0 +1,13 @@
+#include <stdio.h>
+
+static inline __attribute__((always_inline)) int square(int x) {
+    int result = x * x;
+    return result;
+}
+
+int (*square_ptr)(int) = square;
+
+int main() {
+    printf("%d %d\n", square(3), square_ptr(4));
+    return 0;
+}
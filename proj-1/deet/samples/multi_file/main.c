@@ -0,0 +1,9 @@
+#include <stdio.h>
+
+int add(int a, int b);
+
+int main() {
+    int result = add(1, 2);
+    printf("%d\n", result);
+    return 0;
+}
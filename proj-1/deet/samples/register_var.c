@@ -0,0 +1,12 @@
+#include <stdio.h>
+
+int main() {
+    // gcc honors the register keyword at -O0, so there is no stack slot for total, and its
+    // location is a register (DW_OP_reg*) instead
+    register int total = 0;
+    for (int i = 1; i <= 10; i++) {
+        total += i;
+    }
+    printf("%d\n", total);
+    return 0;
+}
@@ -0,0 +1,32 @@
+#include <stdio.h>
+
+struct point {
+    int x;
+    long y;
+};
+
+struct node {
+    int value;
+    struct node *next;
+};
+
+typedef int myint;
+
+int global_int = 5;
+int *global_ptr = &global_int;
+void *global_void_ptr = NULL;
+struct point global_point = {1, 2};
+struct node global_node = {3, NULL};
+int global_arr[10];
+long global_matrix[2][3];
+myint global_myint = 6;
+const int global_const = 7;
+volatile long global_volatile = 8;
+const char *global_str = "hello";
+
+int main() {
+    char **argvp = NULL;
+    struct point *point_ptr = &global_point;
+    printf("%d %p %p %ld\n", *global_ptr, global_void_ptr, (void *) argvp, point_ptr->y);
+    return 0;
+}
@@ -1,54 +1,546 @@
 use crate::debugger_command::DebuggerCommand;
-use crate::inferior::Inferior;
+use crate::dwarf_data::{DwarfData, Error as DwarfError, Location, Type};
+use crate::inferior::{Inferior, Status};
+use nix::sys::signal::Signal;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::collections::BTreeMap;
+use std::fs;
+
+pub struct Breakpoint {
+    /// Number shown to the user (starting from 1, in the order the breakpoints were set)
+    pub number: usize,
+    /// Whether the breakpoint was set at a raw address (`*ADDR`). That's a runtime address, so it
+    /// stays where the user put it rather than moving with the executable.
+    pub raw_address: bool,
+}
 
 pub struct Debugger {
     target: String,
+    /// Arguments to run the target with (from the command line, or the last run command)
+    args: Vec<String>,
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
+    debug_data: DwarfData,
+    /// Breakpoints, keyed by the address of the instruction they stop at
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint_number: usize,
 }
 
 impl Debugger {
     /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        // TODO (milestone 3): initialize the DwarfData
+    pub fn new(target: &str, args: Vec<String>) -> Debugger {
+        let debug_data = match DwarfData::from_file(target) {
+            Ok(val) => val,
+            Err(DwarfError::ErrorOpeningFile) => {
+                println!("Could not open file {}", target);
+                std::process::exit(1);
+            }
+            Err(DwarfError::DwarfFormatError(err)) => {
+                println!(
+                    "Could not load debugging symbols from {}: {:?}",
+                    target, err
+                );
+                std::process::exit(1);
+            }
+        };
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
+        // Keep the history in the current directory if there's no home directory to put it in
+        let history_path = match std::env::var("HOME") {
+            Ok(home) => format!("{}/.deet_history", home),
+            Err(_) => ".deet_history".to_string(),
+        };
         let mut readline = Editor::<()>::new();
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path);
 
         Debugger {
             target: target.to_string(),
+            args,
             history_path,
             readline,
             inferior: None,
+            debug_data,
+            breakpoints: BTreeMap::new(),
+            next_breakpoint_number: 1,
         }
     }
 
     pub fn run(&mut self) {
         loop {
             match self.get_next_command() {
-                DebuggerCommand::Run(args) => {
-                    if let Some(inferior) = Inferior::new(&self.target, &args) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        // TODO (milestone 1): make the inferior run
-                        // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-                        // to the Inferior object
-                    } else {
-                        println!("Error starting subprocess");
+                DebuggerCommand::Run(args) => self.start_inferior(args),
+                DebuggerCommand::Continue => match self.inferior.as_mut() {
+                    Some(inferior) => {
+                        let status = inferior.cont();
+                        self.report_status(status);
                     }
+                    None => println!("The program is not being run (use \"run\" to start it)"),
+                },
+                DebuggerCommand::StepInstruction => match self.inferior.as_mut() {
+                    Some(inferior) => {
+                        let status = inferior.step_instruction();
+                        self.report_step(status);
+                    }
+                    None => println!("The program is not being run (use \"run\" to start it)"),
+                },
+                DebuggerCommand::Step => self.step_line(false),
+                DebuggerCommand::Next => self.step_line(true),
+                DebuggerCommand::Finish => self.finish(),
+                DebuggerCommand::Backtrace => self.print_backtrace(),
+                DebuggerCommand::Print(name) => self.print_variable(&name),
+                DebuggerCommand::Examine(count, addr) => self.examine_memory(count, &addr),
+                DebuggerCommand::InfoRegisters => self.print_registers(),
+                DebuggerCommand::Break(location) => {
+                    self.set_breakpoint(&location);
                 }
+                DebuggerCommand::Delete(number) => self.delete_breakpoint(&number),
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::List => self.list_source(),
                 DebuggerCommand::Quit => {
+                    if let Some(mut inferior) = self.inferior.take() {
+                        println!("Killing running inferior (pid {})", inferior.pid());
+                        inferior.kill();
+                    }
                     return;
                 }
             }
         }
     }
 
+    /// Starts a new inferior (killing the current one, if any) and runs it until it stops.
+    fn start_inferior(&mut self, args: Vec<String>) {
+        // Only one inferior can be debugged at a time
+        if let Some(mut inferior) = self.inferior.take() {
+            inferior.kill();
+        }
+        // Like gdb, run without arguments reuses the previous arguments
+        if !args.is_empty() {
+            self.args = args;
+        }
+        if let Some(inferior) = Inferior::new(&self.target, &self.args) {
+            // Create the inferior
+            self.inferior = Some(inferior);
+            self.install_breakpoints();
+            let status = self.inferior.as_mut().unwrap().cont();
+            self.report_status(status);
+        } else {
+            println!("Error starting subprocess");
+        }
+    }
+
+    /// Installs all of the breakpoints in a newly started inferior. If the executable was loaded
+    /// somewhere other than where the last run was (as position-independent executables are), the
+    /// breakpoints are moved along with it first.
+    fn install_breakpoints(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let old_bias = self.debug_data.load_bias();
+        if let Some(load_address) = inferior.load_address() {
+            self.debug_data.set_load_address(load_address);
+        }
+        let new_bias = self.debug_data.load_bias();
+        if new_bias != old_bias {
+            self.breakpoints =
+                move_breakpoints(std::mem::take(&mut self.breakpoints), old_bias, new_bias);
+        }
+        for (addr, breakpoint) in &self.breakpoints {
+            if inferior.install_breakpoint(*addr).is_err() {
+                println!(
+                    "Warning: could not install breakpoint {} at {:#x}",
+                    breakpoint.number, addr
+                );
+            }
+        }
+    }
+
+    /// Prints why the inferior stopped. If it has terminated, there is no longer an inferior to
+    /// debug.
+    fn report_status(&mut self, status: Result<Status, nix::Error>) {
+        match status {
+            Ok(Status::Stopped(signal, rip)) => match self.breakpoints.get(&rip) {
+                Some(breakpoint) if signal == Signal::SIGTRAP => {
+                    println!(
+                        "Child hit breakpoint {} at {}",
+                        breakpoint.number,
+                        self.describe_location(rip)
+                    );
+                }
+                _ if is_fatal_signal(signal) => {
+                    // The inferior is about to crash, so show how it got there
+                    println!(
+                        "Child received signal {} at {}",
+                        signal,
+                        self.describe_location(rip)
+                    );
+                    self.print_backtrace();
+                }
+                _ => println!(
+                    "Child stopped (signal {}) at {}",
+                    signal,
+                    self.describe_location(rip)
+                ),
+            },
+            Ok(Status::Exited(exit_code)) => {
+                println!("Child exited (status {})", exit_code);
+                self.inferior = None;
+            }
+            Ok(Status::Signaled(signal)) => {
+                println!("Child exited due to signal {}", signal);
+                self.inferior = None;
+            }
+            Err(err) => {
+                println!("Error waiting for child: {}", err);
+                self.inferior = None;
+            }
+        }
+    }
+
+    fn step_line(&mut self, step_over_calls: bool) {
+        match self.inferior.as_mut() {
+            Some(inferior) => {
+                let status = inferior.step_line(&self.debug_data, step_over_calls);
+                self.report_step(status);
+            }
+            None => println!("The program is not being run (use \"run\" to start it)"),
+        }
+    }
+
+    /// Runs until the current function returns, then prints where we ended up and the value the
+    /// function returned (if it returns something that fits in rax).
+    fn finish(&mut self) {
+        let rip = match self
+            .inferior
+            .as_ref()
+            .map(|inferior| inferior.get_registers())
+        {
+            Some(Ok(regs)) => regs.rip as usize,
+            Some(Err(err)) => {
+                println!("Error reading registers: {}", err);
+                return;
+            }
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        let function = self.debug_data.get_function_containing(rip);
+        if function.map(|func| func.name.as_str()) == Some("main") {
+            println!("\"finish\" not meaningful in the outermost frame");
+            return;
+        }
+        let return_type = function.and_then(|func| func.return_type.clone());
+        println!("Run till exit from {}", self.describe_location(rip));
+        let inferior = self.inferior.as_mut().unwrap();
+        let status = inferior.finish();
+        // Return values that fit in a register come back in rax
+        let rax = inferior.get_registers().map(|regs| regs.rax);
+        match status {
+            Ok(Status::Stopped(Signal::SIGTRAP, rip)) if !self.breakpoints.contains_key(&rip) => {
+                println!("{}", self.describe_location(rip));
+                match (return_type, rax) {
+                    (Some(return_type), Ok(rax)) if return_type.size <= WORD_SIZE => println!(
+                        "Value returned is ({}) {}",
+                        return_type.name,
+                        format_value(&return_type, &rax.to_le_bytes()[..return_type.size])
+                    ),
+                    (Some(_), Err(err)) => println!("Error reading registers: {}", err),
+                    _ => {}
+                }
+            }
+            other => self.report_status(other),
+        }
+    }
+
+    fn print_backtrace(&self) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        match inferior.backtrace(&self.debug_data) {
+            Ok(frames) => {
+                for (i, rip) in frames.iter().enumerate() {
+                    // The callers' addresses are return addresses, which point to the
+                    // instruction after the call, and that may already be part of the next line
+                    let addr = if i == 0 { *rip } else { rip - 1 };
+                    println!("#{:<2} {}", i, self.describe_location(addr));
+                }
+            }
+            Err(err) => println!("Error reading the stack: {}", err),
+        }
+    }
+
+    /// Prints the value of a variable that is in scope at the current instruction.
+    fn print_variable(&self, name: &str) {
+        if name.is_empty() {
+            println!("Usage: print <variable>");
+            return;
+        }
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        let rip = match inferior.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(err) => {
+                println!("Error reading registers: {}", err);
+                return;
+            }
+        };
+        // Locals come first, so they shadow globals with the same name
+        let var = match self
+            .debug_data
+            .variables_in_scope(rip)
+            .into_iter()
+            .find(|var| var.name == name)
+        {
+            Some(var) => var,
+            None => {
+                println!("No variable named {} in the current scope", name);
+                return;
+            }
+        };
+        if var.location == Location::Unknown {
+            println!("({}) {} = <optimized out>", var.entity_type.name, name);
+            return;
+        }
+        let value = inferior
+            .variable_address(&var.location, self.debug_data.load_bias())
+            .and_then(|addr| inferior.read_memory(addr, var.entity_type.size));
+        match value {
+            Ok(bytes) => println!(
+                "({}) {} = {}",
+                var.entity_type.name,
+                name,
+                format_value(&var.entity_type, &bytes)
+            ),
+            Err(err) => println!("Error reading {}: {}", name, err),
+        }
+    }
+
+    /// Dumps count words of the inferior's memory, starting at the given (hex) address.
+    fn examine_memory(&self, count: usize, addr: &str) {
+        let addr = match parse_address(addr) {
+            Some(addr) => addr,
+            None => {
+                println!("Usage: x/<count>x <address>");
+                return;
+            }
+        };
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        match inferior.read_memory(addr, count * WORD_SIZE) {
+            Ok(bytes) => {
+                for row in format_memory(addr, &bytes) {
+                    println!("{}", row);
+                }
+            }
+            Err(err) => println!("Cannot access memory at {:#x}: {}", addr, err),
+        }
+    }
+
+    fn print_registers(&self) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        let regs = match inferior.get_registers() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Error reading registers: {}", err);
+                return;
+            }
+        };
+        let registers = [
+            ("rax", regs.rax),
+            ("rbx", regs.rbx),
+            ("rcx", regs.rcx),
+            ("rdx", regs.rdx),
+            ("rsi", regs.rsi),
+            ("rdi", regs.rdi),
+            ("rbp", regs.rbp),
+            ("rsp", regs.rsp),
+            ("r8", regs.r8),
+            ("r9", regs.r9),
+            ("r10", regs.r10),
+            ("r11", regs.r11),
+            ("r12", regs.r12),
+            ("r13", regs.r13),
+            ("r14", regs.r14),
+            ("r15", regs.r15),
+            ("rip", regs.rip),
+            ("eflags", regs.eflags),
+            ("cs", regs.cs),
+            ("ss", regs.ss),
+            ("ds", regs.ds),
+            ("es", regs.es),
+            ("fs", regs.fs),
+            ("gs", regs.gs),
+        ];
+        for (name, value) in registers.iter() {
+            println!("{:<8} {:#018x}  {}", name, value, value);
+        }
+    }
+
+    /// Prints where the inferior ended up after stepping. If something else happened along the way
+    /// (e.g. it hit a breakpoint or exited), says so instead.
+    fn report_step(&mut self, status: Result<Status, nix::Error>) {
+        match status {
+            Ok(Status::Stopped(Signal::SIGTRAP, rip)) if !self.breakpoints.contains_key(&rip) => {
+                println!("{}", self.describe_location(rip));
+            }
+            other => self.report_status(other),
+        }
+    }
+
+    /// Describes an address in the inferior as e.g. "main (/path/to/main.c:6)", falling back to the
+    /// raw address for code we don't have debugging symbols for.
+    fn describe_location(&self, addr: usize) -> String {
+        let function = self.debug_data.get_function_from_addr(addr);
+        let line = self.debug_data.get_line_from_addr(addr);
+        match (function, line) {
+            (Some(function), Some(line)) => format!("{} ({})", function, line),
+            (Some(function), None) => format!("{} ({:#x})", function, addr),
+            _ => format!("{:#x}", addr),
+        }
+    }
+
+    /// Prints the source lines around the line the inferior is stopped at.
+    fn list_source(&self) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run (use \"run\" to start it)");
+                return;
+            }
+        };
+        let rip = match inferior.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(err) => {
+                println!("Error reading registers: {}", err);
+                return;
+            }
+        };
+        let line = match self.debug_data.get_line_from_addr(rip) {
+            Some(line) => line,
+            None => {
+                println!("No line information for {:#x}", rip);
+                return;
+            }
+        };
+        match fs::read_to_string(&line.file) {
+            Ok(source) => {
+                for row in format_source_window(&source, line.number, LIST_CONTEXT) {
+                    println!("{}", row);
+                }
+            }
+            Err(err) => println!("Cannot open source file {}: {}", line.file, err),
+        }
+    }
+
+    /// Records a breakpoint at the given location (see resolve_location for the accepted formats).
+    fn set_breakpoint(&mut self, location: &str) {
+        if location.is_empty() {
+            println!("Usage: break <*address | file:line | function>");
+            return;
+        }
+        let addr = match resolve_location(&self.debug_data, location) {
+            Some(addr) => addr,
+            None => {
+                println!("Could not find location {}", location);
+                return;
+            }
+        };
+        if let Some(existing) = self.breakpoints.get(&addr) {
+            println!(
+                "Breakpoint {} is already set at {:#x}",
+                existing.number, addr
+            );
+            return;
+        }
+        let number = self.next_breakpoint_number;
+        self.next_breakpoint_number += 1;
+        match self.debug_data.get_line_from_addr(addr) {
+            Some(line) => println!("Set breakpoint {} at {:#x} ({})", number, addr, line),
+            None => println!("Set breakpoint {} at {:#x}", number, addr),
+        }
+        if let Some(inferior) = self.inferior.as_mut() {
+            if inferior.install_breakpoint(addr).is_err() {
+                println!("Could not install breakpoint at {:#x}", addr);
+                return;
+            }
+        }
+        self.breakpoints.insert(
+            addr,
+            Breakpoint {
+                number,
+                raw_address: location.starts_with('*'),
+            },
+        );
+    }
+
+    /// Deletes the breakpoint with the given number, removing it from the inferior if it's running.
+    fn delete_breakpoint(&mut self, number: &str) {
+        let number: usize = match number.parse() {
+            Ok(number) => number,
+            Err(_) => {
+                println!("Usage: delete <breakpoint number>");
+                return;
+            }
+        };
+        let addr = match self
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.number == number)
+        {
+            Some((addr, _)) => *addr,
+            None => {
+                println!("No breakpoint number {}", number);
+                return;
+            }
+        };
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(err) = inferior.remove_breakpoint(addr) {
+                println!("Could not remove breakpoint {}: {}", number, err);
+                return;
+            }
+        }
+        self.breakpoints.remove(&addr);
+        println!("Deleted breakpoint {}", number);
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints");
+            return;
+        }
+        let mut breakpoints: Vec<(&usize, &Breakpoint)> = self.breakpoints.iter().collect();
+        breakpoints.sort_by_key(|(_, breakpoint)| breakpoint.number);
+        println!("Num  Address             Location");
+        for (addr, breakpoint) in breakpoints {
+            println!(
+                "{:<4} {:#018x}  {}",
+                breakpoint.number,
+                addr,
+                self.describe_location(*addr)
+            );
+        }
+    }
+
     /// This function prompts the user to enter a command, and continues re-prompting until the user
     /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
     ///
@@ -90,3 +582,365 @@ impl Debugger {
         }
     }
 }
+
+/// Number of lines that list shows on each side of the current line
+const LIST_CONTEXT: usize = 5;
+
+/// Formats the lines of source within context lines of the given (1-based) line number, each
+/// prefixed with its number, marking the given line with an arrow.
+fn format_source_window(source: &str, line_number: usize, context: usize) -> Vec<String> {
+    let first = line_number.saturating_sub(context).max(1);
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| (i + 1, text))
+        .skip(first - 1)
+        .take_while(|(number, _)| *number <= line_number + context)
+        .map(|(number, text)| {
+            let marker = if number == line_number { "->" } else { "  " };
+            format!("{} {:<4} {}", marker, number, text)
+        })
+        .collect()
+}
+
+const WORD_SIZE: usize = 8;
+const WORDS_PER_ROW: usize = 2;
+
+/// Formats memory as rows of hex words (in the inferior's byte order, i.e. little-endian), each
+/// prefixed with the address of its first word, like gdb's x/gx.
+fn format_memory(addr: usize, bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(WORD_SIZE * WORDS_PER_ROW)
+        .enumerate()
+        .map(|(i, row)| {
+            let words: Vec<String> = row
+                .chunks(WORD_SIZE)
+                .map(|word| {
+                    let mut buf = [0u8; WORD_SIZE];
+                    buf[..word.len()].copy_from_slice(word);
+                    format!("{:#018x}", u64::from_le_bytes(buf))
+                })
+                .collect();
+            format!(
+                "{:#x}:  {}",
+                addr + i * WORD_SIZE * WORDS_PER_ROW,
+                words.join("  ")
+            )
+        })
+        .collect()
+}
+
+/// Parses an address written in hex, with or without a leading 0x.
+fn parse_address(addr: &str) -> Option<usize> {
+    let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
+        &addr[2..]
+    } else {
+        addr
+    };
+    usize::from_str_radix(addr_without_0x, 16).ok()
+}
+
+impl Drop for Debugger {
+    /// Makes sure we never leave a running (or stopped) inferior behind, however the debugger
+    /// exits.
+    fn drop(&mut self) {
+        if let Some(mut inferior) = self.inferior.take() {
+            inferior.kill();
+        }
+    }
+}
+
+/// Returns true for signals that kill a program that doesn't handle them, because it did
+/// something wrong (as opposed to e.g. SIGINT or SIGTERM).
+fn is_fatal_signal(signal: Signal) -> bool {
+    matches!(
+        signal,
+        Signal::SIGSEGV | Signal::SIGABRT | Signal::SIGBUS | Signal::SIGFPE | Signal::SIGILL
+    )
+}
+
+/// Formats the raw bytes of a value according to its type. Integers and pointers are decoded;
+/// anything else (structs, arrays, floats) is shown as raw bytes for now.
+fn format_value(entity_type: &Type, bytes: &[u8]) -> String {
+    let is_pointer = entity_type.name.ends_with('*');
+    let is_integer = entity_type.members.is_empty()
+        && entity_type.element_type.is_none()
+        && !entity_type.name.contains("float")
+        && !entity_type.name.contains("double");
+    if (is_pointer || is_integer) && matches!(bytes.len(), 1 | 2 | 4 | 8) {
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let unsigned = u64::from_le_bytes(buf);
+        if is_pointer {
+            return format!("{:#x}", unsigned);
+        }
+        if entity_type.name.contains("unsigned") || entity_type.name == "_Bool" {
+            return unsigned.to_string();
+        }
+        // Sign-extend from the size of the type
+        let shift = 64 - 8 * bytes.len();
+        let signed = ((unsigned << shift) as i64) >> shift;
+        if entity_type.name == "char" {
+            return format!("{} {:?}", signed, signed as u8 as char);
+        }
+        return signed.to_string();
+    }
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("<{} bytes: {}>", bytes.len(), hex.join(" "))
+}
+
+/// Resolves a breakpoint location to an address. The location can be a raw address (`*0x401136`),
+/// a line in a source file (`main.c:6`), or the name of a function (`main`).
+fn resolve_location(debug_data: &DwarfData, location: &str) -> Option<usize> {
+    if let Some(addr) = location.strip_prefix('*') {
+        parse_address(addr)
+    } else if let Some((file, line)) = location.rsplit_once(':') {
+        debug_data.get_addr_for_line(Some(file), line.parse().ok()?)
+    } else {
+        debug_data.get_addr_for_function(None, location)
+    }
+}
+
+/// Moves breakpoints from where the executable was loaded last time (old_bias) to where it's
+/// loaded now (new_bias). Raw address breakpoints stay put, and so does any breakpoint that can't
+/// be moved (with a warning).
+fn move_breakpoints(
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    old_bias: usize,
+    new_bias: usize,
+) -> BTreeMap<usize, Breakpoint> {
+    breakpoints
+        .into_iter()
+        .map(|(addr, breakpoint)| {
+            if breakpoint.raw_address {
+                return (addr, breakpoint);
+            }
+            match addr
+                .checked_sub(old_bias)
+                .and_then(|dwarf_addr| dwarf_addr.checked_add(new_bias))
+            {
+                Some(new_addr) => (new_addr, breakpoint),
+                None => {
+                    println!(
+                        "Warning: could not move breakpoint {} at {:#x} to where the executable is \
+                        now loaded",
+                        breakpoint.number, addr
+                    );
+                    (addr, breakpoint)
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_location() {
+        let debug_data = DwarfData::from_file("samples/multi_file/multi_file")
+            .expect("Could not load samples/multi_file/multi_file. Have you run make?");
+        assert_eq!(resolve_location(&debug_data, "*0x401136"), Some(0x401136));
+        assert_eq!(resolve_location(&debug_data, "*401136"), Some(0x401136));
+        assert_eq!(resolve_location(&debug_data, "*nope"), None);
+        assert_eq!(
+            resolve_location(&debug_data, "main"),
+            debug_data.get_addr_for_function(None, "main")
+        );
+        assert_eq!(
+            resolve_location(&debug_data, "helper.c:2"),
+            debug_data.get_addr_for_line(Some("helper.c"), 2)
+        );
+        assert!(resolve_location(&debug_data, "helper.c:2").is_some());
+        assert_eq!(resolve_location(&debug_data, "helper.c:two"), None);
+        assert_eq!(resolve_location(&debug_data, "no_such_function"), None);
+    }
+
+    #[test]
+    fn test_format_value() {
+        let int = Type::new("int".to_string(), 4);
+        assert_eq!(format_value(&int, &(-5i32).to_le_bytes()), "-5");
+        let unsigned = Type::new("unsigned int".to_string(), 4);
+        assert_eq!(
+            format_value(&unsigned, &u32::MAX.to_le_bytes()),
+            "4294967295"
+        );
+        let long = Type::new("long int".to_string(), 8);
+        assert_eq!(
+            format_value(&long, &1234567890123i64.to_le_bytes()),
+            "1234567890123"
+        );
+        let c = Type::new("char".to_string(), 1);
+        assert_eq!(format_value(&c, b"A"), "65 'A'");
+        let ptr = Type::new("int*".to_string(), 8);
+        assert_eq!(format_value(&ptr, &0x404028u64.to_le_bytes()), "0x404028");
+        let arr = Type::new_array(Type::new("char".to_string(), 1), 2);
+        assert_eq!(format_value(&arr, &[1, 2]), "<2 bytes: 01 02>");
+    }
+
+    #[test]
+    fn test_delete_breakpoint() {
+        let mut debugger = Debugger::new("samples/count", Vec::new());
+        debugger.set_breakpoint("count.c:5");
+        debugger.set_breakpoint("count.c:7");
+        let line_7 = resolve_location(&debugger.debug_data, "count.c:7").unwrap();
+        debugger.delete_breakpoint("1");
+        // Deleting a breakpoint that doesn't exist (any more) is harmless
+        debugger.delete_breakpoint("1");
+        debugger.delete_breakpoint("5");
+        let numbers: Vec<usize> = debugger.breakpoints.values().map(|bp| bp.number).collect();
+        assert_eq!(numbers, vec![2]);
+
+        debugger.start_inferior(Vec::new());
+        let inferior = debugger.inferior.as_mut().unwrap();
+        assert_eq!(inferior.get_registers().unwrap().rip as usize, line_7);
+        // Deleting a breakpoint while the inferior is running restores the original instruction,
+        // and the inferior runs to completion
+        debugger.delete_breakpoint("2");
+        let status = debugger.inferior.as_mut().unwrap().cont().unwrap();
+        assert!(matches!(status, Status::Exited(0)));
+    }
+
+    #[test]
+    fn test_kill_inferior_on_drop() {
+        let mut debugger = Debugger::new("samples/count", Vec::new());
+        debugger.set_breakpoint("main");
+        debugger.start_inferior(Vec::new());
+        let pid = debugger.inferior.as_ref().unwrap().pid();
+        assert!(nix::sys::signal::kill(pid, None).is_ok());
+        drop(debugger);
+        // The child has been killed and reaped, so its pid no longer exists
+        assert_eq!(
+            nix::sys::signal::kill(pid, None),
+            Err(nix::Error::Sys(nix::errno::Errno::ESRCH))
+        );
+    }
+
+    #[test]
+    fn test_format_source_window() {
+        let source = fs::read_to_string("samples/count.c").unwrap();
+        assert_eq!(
+            format_source_window(&source, 5, 2),
+            vec![
+                "   3    int main() {",
+                "   4        printf(\"1\\n\");",
+                "-> 5        printf(\"2\\n\");",
+                "   6        printf(\"3\\n\");",
+                "   7        printf(\"4\\n\");",
+            ]
+        );
+        // The window is cut off at the start and end of the file
+        let window = format_source_window(&source, 2, LIST_CONTEXT);
+        assert_eq!(window.len(), 7);
+        assert!(window[0].starts_with("   1 "));
+        assert!(window[1].starts_with("-> 2 "));
+        let window = format_source_window(&source, 9, LIST_CONTEXT);
+        assert_eq!(window.len(), 7);
+        assert!(window[6].starts_with("   10   }"));
+    }
+
+    #[test]
+    fn test_finish() {
+        let debug_data = DwarfData::from_file("samples/multi_file/multi_file")
+            .expect("Could not load samples/multi_file/multi_file. Have you run make?");
+        let mut inferior = Inferior::new("samples/multi_file/multi_file", &Vec::new()).unwrap();
+        let addr = debug_data.get_addr_for_line(Some("main.c"), 6).unwrap();
+        inferior.install_breakpoint(addr).unwrap();
+        inferior.cont().unwrap();
+        // Step into add, then finish back out to the call in main
+        inferior.step_line(&debug_data, false).unwrap();
+        let rip = inferior.get_registers().unwrap().rip as usize;
+        assert_eq!(debug_data.get_function_from_addr(rip).unwrap(), "add");
+        let rip = match inferior.finish().unwrap() {
+            Status::Stopped(Signal::SIGTRAP, rip) => rip,
+            _ => panic!("Expected the inferior to stop after add returned"),
+        };
+        assert_eq!(debug_data.get_function_from_addr(rip).unwrap(), "main");
+        let line = debug_data.get_line_from_addr(rip).unwrap();
+        assert!(line.file.ends_with("main.c"));
+        assert_eq!(line.number, 6);
+        assert_eq!(inferior.get_registers().unwrap().rax, 3);
+        // The temporary breakpoint at the return address is gone
+        assert!(matches!(inferior.cont().unwrap(), Status::Exited(0)));
+    }
+
+    #[test]
+    fn test_format_memory() {
+        let bytes: Vec<u8> = (1..=24).collect();
+        assert_eq!(
+            format_memory(0x404020, &bytes),
+            vec![
+                "0x404020:  0x0807060504030201  0x100f0e0d0c0b0a09",
+                "0x404030:  0x1817161514131211",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_global_memory() {
+        let debug_data = DwarfData::from_file("samples/types")
+            .expect("Could not load samples/types. Have you run make?");
+        let inferior = Inferior::new("samples/types", &Vec::new()).unwrap();
+        // Globals are initialized as soon as the program is loaded
+        let var = debug_data.get_variable("global_int").unwrap();
+        let addr = inferior.variable_address(&var.location, 0).unwrap();
+        assert_eq!(inferior.read_memory(addr, 4).unwrap(), vec![5, 0, 0, 0]);
+        // Reads don't have to be a whole number of words
+        let var = debug_data.get_variable("global_point").unwrap();
+        let addr = inferior.variable_address(&var.location, 0).unwrap();
+        let bytes = inferior.read_memory(addr, 9).unwrap();
+        assert_eq!(bytes, vec![1, 0, 0, 0, 0, 0, 0, 0, 2]);
+        let mut inferior = inferior;
+        inferior.kill();
+    }
+
+    #[test]
+    fn test_read_local_variable() {
+        let debug_data = DwarfData::from_file("samples/multi_file/multi_file")
+            .expect("Could not load samples/multi_file/multi_file. Have you run make?");
+        let mut inferior = Inferior::new("samples/multi_file/multi_file", &Vec::new()).unwrap();
+        // Line 7 is right after result = add(1, 2)
+        let addr = debug_data.get_addr_for_line(Some("main.c"), 7).unwrap();
+        inferior.install_breakpoint(addr).unwrap();
+        inferior.cont().unwrap();
+        let var = debug_data.get_local_variable("main", "result").unwrap();
+        let addr = inferior.variable_address(&var.location, 0).unwrap();
+        let bytes = inferior.read_memory(addr, var.entity_type.size).unwrap();
+        assert_eq!(format_value(&var.entity_type, &bytes), "3");
+        inferior.kill();
+    }
+
+    #[test]
+    fn test_move_breakpoints() {
+        let mut breakpoints = BTreeMap::new();
+        breakpoints.insert(
+            0x555555555139,
+            Breakpoint {
+                number: 1,
+                raw_address: false,
+            },
+        );
+        breakpoints.insert(
+            0x1139,
+            Breakpoint {
+                number: 2,
+                raw_address: true,
+            },
+        );
+        // Can't be moved down by the old bias
+        breakpoints.insert(
+            0x10,
+            Breakpoint {
+                number: 3,
+                raw_address: false,
+            },
+        );
+        let moved = move_breakpoints(breakpoints, 0x555555554000, 0x7f0000000000);
+        let numbers: Vec<(usize, usize)> = moved
+            .iter()
+            .map(|(addr, breakpoint)| (*addr, breakpoint.number))
+            .collect();
+        assert_eq!(numbers, vec![(0x10, 3), (0x1139, 2), (0x7f0000001139, 1)]);
+    }
+}
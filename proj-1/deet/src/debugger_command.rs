@@ -1,6 +1,20 @@
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
+    Break(String),
+    Continue,
+    StepInstruction,
+    Step,
+    Next,
+    Finish,
+    Backtrace,
+    Print(String),
+    /// Dump the given number of words of memory, starting at an address
+    Examine(usize, String),
+    InfoRegisters,
+    InfoBreakpoints,
+    Delete(String),
+    List,
 }
 
 impl DebuggerCommand {
@@ -13,6 +27,35 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
+            "p" | "print" => Some(DebuggerCommand::Print(tokens[1..].join(" "))),
+            "i" | "info" => match tokens.get(1) {
+                Some(&"r") | Some(&"reg") | Some(&"registers") => {
+                    Some(DebuggerCommand::InfoRegisters)
+                }
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                _ => None,
+            },
+            // x/<n>x <addr> (the count and format are optional, and hex is the only format)
+            cmd if cmd == "x" || cmd.starts_with("x/") => {
+                let count = cmd[1..].trim_start_matches('/').trim_end_matches('x');
+                let count = if count.is_empty() {
+                    1
+                } else {
+                    count.parse().ok()?
+                };
+                Some(DebuggerCommand::Examine(count, tokens[1..].join(" ")))
+            }
+            "d" | "delete" => Some(DebuggerCommand::Delete(tokens[1..].join(" "))),
+            "l" | "list" => Some(DebuggerCommand::List),
+            "b" | "break" => Some(DebuggerCommand::Break(tokens[1..].join(" "))),
             // Default case:
             _ => None,
         }
@@ -1,8 +1,20 @@
 use crate::gimli_wrapper;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSegment};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::convert::TryInto;
-use std::{fmt, fs};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::time::SystemTime;
+use std::{env, fmt, fs, io, path};
+
+// Bump this whenever the layout of the parsed structs below changes, so that stale caches are
+// ignored instead of being misinterpreted
+const CACHE_VERSION: u32 = 5;
+
+const PAGE_SIZE: usize = 4096;
 
 #[derive(Debug)]
 pub enum Error {
@@ -13,6 +25,10 @@ pub enum Error {
 pub struct DwarfData {
     files: Vec<File>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    // Address that the executable expects to be loaded at (0 for position-independent executables)
+    base_address: usize,
+    // Difference between the runtime addresses in the inferior and the addresses in the DWARF info
+    load_bias: usize,
 }
 
 impl fmt::Debug for DwarfData {
@@ -38,12 +54,64 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        // Parsing the DWARF info is slow for large binaries, so reuse the result from a previous
+        // run if the binary hasn't changed since then
+        let mtime = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let cache = cache_dir().zip(mtime);
+        let cached_files = cache
+            .as_ref()
+            .and_then(|(cache_dir, mtime)| load_cache(cache_dir, path, *mtime));
+        let files = match cached_files {
+            Some(files) => files,
+            None => {
+                let files = gimli_wrapper::load_file(&object, endian)?;
+                if let Some((cache_dir, mtime)) = &cache {
+                    save_cache(cache_dir, path, *mtime, &files);
+                }
+                files
+            }
+        };
+        // The lowest loadable segment ends up at the start of the executable's first mapping
+        let base_address = object
+            .segments()
+            .map(|segment| segment.address() as usize)
+            .min()
+            .unwrap_or(0)
+            & !(PAGE_SIZE - 1);
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
+            files,
             addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            base_address,
+            load_bias: 0,
         })
     }
 
+    /// Records where the executable was actually loaded in the inferior (the start of its first
+    /// mapping in /proc/{pid}/maps). Position-independent executables are loaded at a random
+    /// address, so from then on, addresses passed in and returned are translated between runtime
+    /// addresses and the addresses in the DWARF info.
+    pub fn set_load_address(&mut self, load_address: usize) {
+        self.load_bias = load_address.saturating_sub(self.base_address);
+    }
+
+    /// Returns the difference between runtime addresses and the addresses in the DWARF info.
+    pub fn load_bias(&self) -> usize {
+        self.load_bias
+    }
+
+    /// Translates a runtime address in the inferior to an address in the DWARF info.
+    fn to_dwarf_addr(&self, runtime_addr: usize) -> Option<usize> {
+        runtime_addr.checked_sub(self.load_bias)
+    }
+
+    /// Translates an address in the DWARF info to a runtime address in the inferior.
+    fn to_runtime_addr(&self, dwarf_addr: usize) -> usize {
+        dwarf_addr + self.load_bias
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
         self.files.iter().find(|f| {
@@ -57,29 +125,25 @@ impl DwarfData {
             Some(filename) => self.get_target_file(filename)?,
             None => self.files.get(0)?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        Some(self.to_runtime_addr(target_file.get_addr_for_line(line_number)?))
     }
 
     #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
         match file {
             Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
-                    .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
+                self.to_runtime_addr(
+                    self.get_target_file(filename)?
+                        .functions
+                        .iter()
+                        .find(|func| func.name == func_name)?
+                        .address,
+                ),
             ),
             None => {
                 for file in &self.files {
                     if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
+                        return Some(self.to_runtime_addr(func.address));
                     }
                 }
                 None
@@ -91,7 +155,7 @@ impl DwarfData {
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(self.to_dwarf_addr(curr_addr)?.try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
@@ -104,11 +168,77 @@ impl DwarfData {
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(self.to_dwarf_addr(curr_addr)?.try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        let function = frame.function?;
+        // Show Rust/C++ symbols the way they appear in source, falling back to the raw (mangled)
+        // name if demangling fails
+        let name = function.demangle().or_else(|_| function.raw_name()).ok()?;
+        Some(name.to_string())
+    }
+
+    /// Returns the line table row with the greatest address that is <= the given address, i.e. the
+    /// statement that the instruction at addr belongs to. Unlike get_line_from_addr, this only
+    /// uses our own line tables, and the returned Line has the address of the start of the
+    /// statement.
+    #[allow(dead_code)]
+    pub fn nearest_line_before(&self, addr: usize) -> Option<Line> {
+        let addr = self.to_dwarf_addr(addr)?;
+        let line = self
+            .files
+            .iter()
+            .filter_map(|file| file.nearest_line_before(addr))
+            .max_by_key(|line| line.address)?;
+        Some(Line {
+            address: self.to_runtime_addr(line.address),
+            ..line.clone()
+        })
+    }
+
+    /// Returns the first global variable with the given name, searching every file.
+    #[allow(dead_code)]
+    pub fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Returns the first variable with the given name that is local to (or a parameter of) the
+    /// given function.
+    #[allow(dead_code)]
+    pub fn get_local_variable(&self, func_name: &str, var_name: &str) -> Option<&Variable> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .filter(|func| func.name == func_name)
+            .flat_map(|func| func.variables.iter())
+            .find(|var| var.name == var_name)
+    }
+
+    /// Returns the function whose code contains the given address.
+    #[allow(dead_code)]
+    pub fn get_function_containing(&self, addr: usize) -> Option<&Function> {
+        let addr = self.to_dwarf_addr(addr)?;
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address <= addr && addr < func.address + func.text_length)
+    }
+
+    /// Returns the variables visible at the given address: the locals and parameters of the
+    /// function containing it (if any), followed by all global variables.
+    #[allow(dead_code)]
+    pub fn variables_in_scope(&self, addr: usize) -> Vec<&Variable> {
+        let function = self.get_function_containing(addr);
+        let locals = function.into_iter().flat_map(|func| func.variables.iter());
+        let globals = self
+            .files
+            .iter()
+            .flat_map(|file| file.global_variables.iter());
+        locals.chain(globals).collect()
     }
 
     #[allow(dead_code)]
@@ -148,10 +278,117 @@ impl DwarfData {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+struct DwarfCache {
+    version: u32,
+    path: path::PathBuf,
+    mtime: SystemTime,
+    files: Vec<File>,
+}
+
+/// Returns the per-user directory that caches are kept in ($XDG_CACHE_HOME/deet, or
+/// ~/.cache/deet), creating it if it doesn't exist yet. The caches decide where breakpoints go and
+/// where variables are read from, so they mustn't live anywhere other users can write to (like the
+/// shared temp directory).
+fn cache_dir() -> Option<path::PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if path::Path::new(&dir).is_absolute() => path::PathBuf::from(dir),
+        _ => path::PathBuf::from(env::var_os("HOME")?).join(".cache"),
+    };
+    let dir = base.join("deet");
+    create_private_dir(&dir).ok()?;
+    Some(dir)
+}
+
+/// Creates dir (and any missing parents) readable only by us, and checks that (if it already
+/// existed) it belongs to us and nobody else can write to it
+fn create_private_dir(dir: &path::Path) -> io::Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    let metadata = fs::metadata(dir)?;
+    if metadata.uid() != nix::unistd::geteuid().as_raw() || metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "cache directory is writable by other users",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the location of the cache file for the given binary within cache_dir
+fn cache_path(cache_dir: &path::Path, binary: &path::Path) -> path::PathBuf {
+    let mut hasher = DefaultHasher::new();
+    binary.hash(&mut hasher);
+    cache_dir.join(format!("{:016x}.cache", hasher.finish()))
+}
+
+/// Returns the cached parse of the given binary, if there is a cache that was created from this
+/// binary (with this modification time) by this version of the parser. Cache files that belong
+/// to another user are never trusted.
+fn load_cache(cache_dir: &path::Path, binary: &str, mtime: SystemTime) -> Option<Vec<File>> {
+    let binary = fs::canonicalize(binary).ok()?;
+    let mut file = fs::File::open(cache_path(cache_dir, &binary)).ok()?;
+    if file.metadata().ok()?.uid() != nix::unistd::geteuid().as_raw() {
+        return None;
+    }
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).ok()?;
+    let cache: DwarfCache = bincode::deserialize(&contents).ok()?;
+    if cache.version != CACHE_VERSION || cache.path != binary || cache.mtime != mtime {
+        return None;
+    }
+    Some(cache.files)
+}
+
+/// Saves the parsed DWARF info for the given binary in cache_dir. The cache is only an
+/// optimization, so failing to write it isn't an error.
+fn save_cache(cache_dir: &path::Path, binary: &str, mtime: SystemTime, files: &[File]) {
+    let binary = match fs::canonicalize(binary) {
+        Ok(binary) => binary,
+        Err(_) => return,
+    };
+    let cache_path = cache_path(cache_dir, &binary);
+    let cache = DwarfCache {
+        version: CACHE_VERSION,
+        path: binary,
+        mtime,
+        files: files.to_vec(),
+    };
+    if let Ok(contents) = bincode::serialize(&cache) {
+        // Write to a temporary file and rename it into place, so that a concurrent load never
+        // sees a partially written cache. create_new makes sure we never write through something
+        // that is already at that path (such as a symlink).
+        let tmp_path = cache_path.with_extension(format!("tmp{}", std::process::id()));
+        let written = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp_path)
+            .and_then(|mut tmp_file| tmp_file.write_all(&contents));
+        match written {
+            Ok(()) => {
+                if fs::rename(&tmp_path, &cache_path).is_err() {
+                    let _ = fs::remove_file(&tmp_path);
+                }
+            }
+            // Only clean up the file if it's ours (i.e. we created it but couldn't write it)
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+                let _ = fs::remove_file(&tmp_path);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub members: Vec<Member>, // Fields of a struct type (empty for other types)
+    pub element_type: Option<Box<Type>>, // Type of each element, if this is an array type
+    pub element_count: usize, // Number of elements, if this is an array type
 }
 
 impl Type {
@@ -159,14 +396,47 @@ impl Type {
         Type {
             name: name,
             size: size,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_array(element_type: Type, element_count: usize) -> Self {
+        // For an array of arrays, the new dimension goes before the existing ones (e.g. an
+        // array of 2 int[3]s is an int[2][3])
+        let name = match element_type.name.find('[') {
+            Some(index) => format!(
+                "{}[{}]{}",
+                &element_type.name[..index],
+                element_count,
+                &element_type.name[index..]
+            ),
+            None => format!("{}[{}]", element_type.name, element_count),
+        };
+        Type {
+            name,
+            size: element_type.size * element_count,
+            element_type: Some(Box::new(element_type)),
+            element_count,
+            ..Default::default()
         }
     }
 }
 
-#[derive(Clone)]
+// For fields of struct types
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: String,
+    pub entity_type: Type,
+    pub offset: usize, // Byte offset from the start of the struct
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// The variable has a location that we don't know how to interpret, e.g. because it lives in
+    /// a register
+    Unknown,
 }
 
 impl fmt::Display for Location {
@@ -174,6 +444,7 @@ impl fmt::Display for Location {
         match *self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Unknown => write!(f, "optimized out / unknown location"),
         }
     }
 }
@@ -185,7 +456,7 @@ impl fmt::Debug for Location {
 }
 
 // For variables and formal parameters
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Variable {
     pub name: String,
     pub entity_type: Type,
@@ -193,16 +464,18 @@ pub struct Variable {
     pub line_number: usize, // Line number in source file
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Function {
     pub name: String,
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    /// None for functions that return void
+    pub return_type: Option<Type>,
     pub variables: Vec<Variable>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct File {
     pub name: String,
     pub global_variables: Vec<Variable>,
@@ -210,7 +483,35 @@ pub struct File {
     pub lines: Vec<Line>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl File {
+    /// Returns the lowest address for the given line. If the line has no code, uses the next line
+    /// after it that does. The line table isn't necessarily sorted by line number (and a line may
+    /// appear several times, e.g. for loop conditions), so this scans all of the rows.
+    fn get_addr_for_line(&self, line_number: usize) -> Option<usize> {
+        let target_line = self
+            .lines
+            .iter()
+            .map(|line| line.number)
+            .filter(|number| *number >= line_number)
+            .min()?;
+        self.lines
+            .iter()
+            .filter(|line| line.number == target_line)
+            .map(|line| line.address)
+            .min()
+    }
+
+    /// Returns the row with the greatest address that is <= addr. If several rows share that
+    /// address, the last one wins, as it does when the line program is executed.
+    fn nearest_line_before(&self, addr: usize) -> Option<&Line> {
+        self.lines
+            .iter()
+            .filter(|line| line.address <= addr)
+            .max_by_key(|line| line.address)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Line {
     pub file: String,
     pub number: usize,
@@ -223,4 +524,403 @@ impl fmt::Display for Line {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn load_sample(name: &str) -> DwarfData {
+        DwarfData::from_file(&format!("samples/{}", name)).unwrap_or_else(|_| {
+            panic!(
+                "Could not load debugging symbols from samples/{}. Have you run make?",
+                name
+            )
+        })
+    }
+
+    fn find_global<'a>(data: &'a DwarfData, name: &str) -> &'a Variable {
+        data.get_variable(name)
+            .unwrap_or_else(|| panic!("Expected to find global variable {}", name))
+    }
+
+    fn find_local<'a>(data: &'a DwarfData, func_name: &str, name: &str) -> &'a Variable {
+        data.get_local_variable(func_name, name)
+            .unwrap_or_else(|| panic!("Expected to find variable {} in {}", name, func_name))
+    }
+
+    #[test]
+    fn test_get_variable() {
+        let data = load_sample("types");
+        let global_int = data.get_variable("global_int").unwrap();
+        assert_eq!(global_int.entity_type.name, "int");
+        assert!(matches!(global_int.location, Location::Address(_)));
+        // Locals aren't globals
+        assert!(data.get_variable("argvp").is_none());
+        assert!(data.get_variable("no_such_variable").is_none());
+
+        let argvp = data.get_local_variable("main", "argvp").unwrap();
+        assert_eq!(argvp.entity_type.name, "char**");
+        assert!(matches!(argvp.location, Location::FramePointerOffset(_)));
+        assert!(data.get_local_variable("main", "global_int").is_none());
+        assert!(data
+            .get_local_variable("no_such_function", "argvp")
+            .is_none());
+
+        // Parameters are found as local variables too
+        let data = load_sample("multi_file/multi_file");
+        assert!(data.get_local_variable("add", "a").is_some());
+        assert!(data.get_local_variable("add", "sum").is_some());
+    }
+
+    #[test]
+    fn test_variables_in_scope() {
+        let data = load_sample("multi_file/multi_file");
+        let names = |addr| -> Vec<String> {
+            data.variables_in_scope(addr)
+                .iter()
+                .map(|var| var.name.clone())
+                .collect()
+        };
+        let add_addr = data.get_addr_for_function(None, "add").unwrap();
+        let in_add = names(add_addr + 1);
+        assert!(in_add.contains(&"a".to_string()));
+        assert!(in_add.contains(&"sum".to_string()));
+        assert!(!in_add.contains(&"result".to_string()));
+        let main_addr = data.get_addr_for_function(None, "main").unwrap();
+        assert_eq!(names(main_addr), vec!["result".to_string()]);
+
+        // Outside of any function, only globals are visible
+        let data = load_sample("types");
+        let outside = data.variables_in_scope(0);
+        assert!(outside.iter().any(|var| var.name == "global_int"));
+        assert!(outside.iter().all(|var| var.name != "argvp"));
+    }
+
+    #[test]
+    fn test_function_return_types() {
+        let data = load_sample("multi_file/multi_file");
+        let add = data.get_addr_for_function(None, "add").unwrap();
+        let return_type = data
+            .get_function_containing(add)
+            .unwrap()
+            .return_type
+            .as_ref();
+        assert_eq!(return_type.unwrap().name, "int");
+        let data = load_sample("function_calls");
+        let func3 = data.get_addr_for_function(None, "func3").unwrap();
+        assert_eq!(
+            data.get_function_containing(func3).unwrap().return_type,
+            None
+        );
+    }
+
+    #[test]
+    fn test_names_from_abstract_origin() {
+        let data = load_sample("inline");
+        // The out-of-line copy of square (used for square_ptr) only has a DW_AT_abstract_origin
+        // pointing to the abstract instance of the function, which has the name
+        let square = data
+            .files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.name == "square" && func.address != 0)
+            .expect("Expected to find the out-of-line copy of square");
+        assert_eq!(square.line_number, 3);
+        let names: Vec<&str> = square
+            .variables
+            .iter()
+            .map(|var| var.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["x", "result"]);
+        assert!(square
+            .variables
+            .iter()
+            .all(|var| var.entity_type.name == "int"));
+        assert!(data
+            .files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .all(|func| !func.name.is_empty()));
+    }
 
+    #[test]
+    fn test_lines_for_multi_file_build() {
+        let data = load_sample("multi_file/multi_file");
+        for name in &["main.c", "helper.c"] {
+            let file = data.get_target_file(name).unwrap();
+            assert!(!file.lines.is_empty(), "No line numbers for {}", name);
+        }
+        let add_line = data.get_addr_for_line(Some("helper.c"), 2).unwrap();
+        let line = data.get_line_from_addr(add_line).unwrap();
+        assert_eq!(line.number, 2);
+        assert!(line.file.ends_with("helper.c"));
+    }
+
+    #[test]
+    fn test_dwarf5_line_numbers() {
+        let data = load_sample("dwarf5");
+        assert_eq!(data.files.len(), 1);
+        let file = data.get_target_file("dwarf5.c").unwrap();
+        assert_eq!(file.name, "samples/dwarf5.c");
+        let numbers: Vec<usize> = file.lines.iter().map(|line| line.number).collect();
+        assert!(
+            numbers.contains(&6) && numbers.contains(&12),
+            "{:?}",
+            numbers
+        );
+        let line = data
+            .get_line_from_addr(data.get_addr_for_line(Some("dwarf5.c"), 7).unwrap())
+            .unwrap();
+        assert!(line.file.ends_with("samples/dwarf5.c"));
+        assert_eq!(line.number, 7);
+        let increment = data.get_addr_for_function(None, "increment").unwrap();
+        assert_eq!(data.get_line_from_addr(increment).unwrap().number, 5);
+        assert_eq!(
+            data.get_variable("counter").unwrap().entity_type.name,
+            "int"
+        );
+    }
+
+    #[test]
+    fn test_unknown_location() {
+        let data = load_sample("register_var");
+        // total is kept in a register, which get_location doesn't understand
+        let total = data.get_local_variable("main", "total").unwrap();
+        assert_eq!(total.location, Location::Unknown);
+        assert_eq!(total.entity_type.name, "int");
+        assert_eq!(total.line_number, 6);
+        let i = data.get_local_variable("main", "i").unwrap();
+        assert!(matches!(i.location, Location::FramePointerOffset(_)));
+    }
+
+    #[test]
+    fn test_get_addr_for_line_out_of_order() {
+        let line = |number, address| Line {
+            file: "test.c".to_string(),
+            number,
+            address,
+        };
+        let file = File {
+            name: "test.c".to_string(),
+            global_variables: Vec::new(),
+            functions: Vec::new(),
+            lines: vec![
+                line(5, 0x40),
+                line(3, 0x20),
+                line(7, 0x30),
+                line(3, 0x10),
+                line(5, 0x50),
+            ],
+        };
+        assert_eq!(file.get_addr_for_line(3), Some(0x10));
+        assert_eq!(file.get_addr_for_line(5), Some(0x40));
+        // Line 4 has no code, so the breakpoint should go on line 5
+        assert_eq!(file.get_addr_for_line(4), Some(0x40));
+        assert_eq!(file.get_addr_for_line(1), Some(0x10));
+        assert_eq!(file.get_addr_for_line(8), None);
+    }
+
+    #[test]
+    fn test_cached_load_matches_full_parse() {
+        let path = "samples/types";
+        // Parse the binary directly, bypassing the cache
+        let file = fs::File::open(path).unwrap();
+        let mmap = unsafe { memmap::Mmap::map(&file).unwrap() };
+        let object = object::File::parse(&mmap).unwrap();
+        let parsed = gimli_wrapper::load_file(&object, gimli::RunTimeEndian::Little).unwrap();
+
+        // Use a scratch cache directory of our own, rather than the real one
+        let cache_dir = path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(format!("test-cache-{}", std::process::id()));
+        create_private_dir(&cache_dir).unwrap();
+        let mtime = file.metadata().unwrap().modified().unwrap();
+        assert!(load_cache(&cache_dir, path, mtime).is_none());
+        save_cache(&cache_dir, path, mtime, &parsed);
+        assert_eq!(load_cache(&cache_dir, path, mtime).unwrap(), parsed);
+        let mode = fs::metadata(&cache_dir).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        // A cache from an older version of the binary must not be used
+        let stale_mtime = mtime - std::time::Duration::from_secs(1);
+        assert!(load_cache(&cache_dir, path, stale_mtime).is_none());
+
+        // Saving again mustn't write through anything already sitting at the temporary path
+        let binary = fs::canonicalize(path).unwrap();
+        let tmp_path =
+            cache_path(&cache_dir, &binary).with_extension(format!("tmp{}", std::process::id()));
+        let target = cache_dir.join("target");
+        fs::write(&target, b"untouched").unwrap();
+        std::os::unix::fs::symlink(&target, &tmp_path).unwrap();
+        save_cache(&cache_dir, path, mtime, &parsed);
+        assert_eq!(fs::read(&target).unwrap(), b"untouched");
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_bias() {
+        let mut data = load_sample("types");
+        // samples are built with -no-pie, so they expect to be loaded at the usual 0x400000
+        assert_eq!(data.base_address, 0x400000);
+        let main_addr = data.get_addr_for_function(None, "main").unwrap();
+        let main_line = data.get_line_from_addr(main_addr).unwrap().number;
+
+        // Pretend that the executable was loaded 0x1000 bytes higher than it expected
+        data.set_load_address(0x401000);
+        assert_eq!(data.load_bias, 0x1000);
+        assert_eq!(data.to_dwarf_addr(0x401234), Some(0x400234));
+        assert_eq!(data.to_runtime_addr(0x400234), 0x401234);
+        assert_eq!(data.to_dwarf_addr(0x10), None);
+        assert_eq!(
+            data.get_addr_for_function(None, "main").unwrap(),
+            main_addr + 0x1000
+        );
+        assert_eq!(
+            data.get_function_from_addr(main_addr + 0x1000).unwrap(),
+            "main"
+        );
+        assert_eq!(
+            data.get_line_from_addr(main_addr + 0x1000).unwrap().number,
+            main_line
+        );
+        let locals = data.variables_in_scope(main_addr + 0x1000);
+        assert!(locals.iter().any(|var| var.name == "argvp"));
+
+        // A position-independent executable expects to be loaded at 0, so the bias is the load
+        // address itself
+        data.base_address = 0;
+        data.set_load_address(0x555555554000);
+        assert_eq!(data.to_dwarf_addr(0x555555555139), Some(0x1139));
+    }
+
+    #[test]
+    fn test_nearest_line_before() {
+        let line = |number, address| Line {
+            file: "test.c".to_string(),
+            number,
+            address,
+        };
+        let file = File {
+            name: "test.c".to_string(),
+            lines: vec![
+                line(2, 0x10),
+                line(3, 0x18),
+                line(4, 0x18),
+                line(6, 0x30),
+                line(3, 0x40),
+            ],
+            ..Default::default()
+        };
+        let nearest = |addr| file.nearest_line_before(addr).map(|line| line.number);
+        assert_eq!(nearest(0x8), None);
+        assert_eq!(nearest(0x10), Some(2));
+        assert_eq!(nearest(0x14), Some(2));
+        // Line 3 has no instructions of its own, so line 4 owns 0x18
+        assert_eq!(nearest(0x18), Some(4));
+        assert_eq!(nearest(0x2f), Some(4));
+        assert_eq!(nearest(0x30), Some(6));
+        assert_eq!(nearest(0x1000), Some(3));
+
+        // The first instruction after main's prologue belongs to the first statement in main
+        let data = load_sample("multi_file/multi_file");
+        let main_line = data.get_addr_for_line(Some("main.c"), 6).unwrap();
+        let nearest = data.nearest_line_before(main_line + 1).unwrap();
+        assert_eq!(nearest.number, 6);
+        assert_eq!(nearest.address, main_line);
+    }
+
+    #[test]
+    fn test_demangle_function_names() {
+        assert_eq!(
+            addr2line::demangle_auto("_ZN3foo3barE".into(), Some(addr2line::gimli::DW_LANG_Rust)),
+            "foo::bar"
+        );
+        assert_eq!(
+            addr2line::demangle_auto(
+                "_Z3fooi".into(),
+                Some(addr2line::gimli::DW_LANG_C_plus_plus)
+            ),
+            "foo(int)"
+        );
+        // C function names aren't mangled, so they should come back unchanged
+        assert_eq!(
+            addr2line::demangle_auto("main".into(), Some(addr2line::gimli::DW_LANG_C99)),
+            "main"
+        );
+        let data = load_sample("types");
+        let main_addr = data.get_addr_for_function(None, "main").unwrap();
+        assert_eq!(data.get_function_from_addr(main_addr).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_pointer_types() {
+        let data = load_sample("types");
+        let global_ptr = find_global(&data, "global_ptr");
+        assert_eq!(global_ptr.entity_type.name, "int*");
+        assert_eq!(global_ptr.entity_type.size, 8);
+        assert_eq!(
+            find_global(&data, "global_void_ptr").entity_type.name,
+            "void*"
+        );
+        let argvp = find_local(&data, "main", "argvp");
+        assert_eq!(argvp.entity_type.name, "char**");
+        assert_eq!(argvp.entity_type.size, 8);
+    }
+
+    #[test]
+    fn test_struct_types() {
+        let data = load_sample("types");
+        let point = &find_global(&data, "global_point").entity_type;
+        assert_eq!(point.name, "struct point");
+        assert_eq!(point.size, 16);
+        let members: Vec<(&str, &str, usize)> = point
+            .members
+            .iter()
+            .map(|m| (m.name.as_str(), m.entity_type.name.as_str(), m.offset))
+            .collect();
+        assert_eq!(members, vec![("x", "int", 0), ("y", "long int", 8)]);
+        assert_eq!(
+            find_local(&data, "main", "point_ptr").entity_type.name,
+            "struct point*"
+        );
+        // Self-referential structs should still resolve
+        let node = &find_global(&data, "global_node").entity_type;
+        assert_eq!(node.name, "struct node");
+        assert_eq!(node.members[1].entity_type.name, "struct node*");
+    }
+
+    #[test]
+    fn test_array_types() {
+        let data = load_sample("types");
+        let arr = &find_global(&data, "global_arr").entity_type;
+        assert_eq!(arr.name, "int[10]");
+        assert_eq!(arr.size, 40);
+        assert_eq!(arr.element_count, 10);
+        assert_eq!(arr.element_type.as_ref().unwrap().name, "int");
+
+        let matrix = &find_global(&data, "global_matrix").entity_type;
+        assert_eq!(matrix.name, "long int[2][3]");
+        assert_eq!(matrix.size, 48);
+        assert_eq!(matrix.element_count, 2);
+        assert_eq!(matrix.element_type.as_ref().unwrap().name, "long int[3]");
+    }
+
+    #[test]
+    fn test_typedef_and_qualified_types() {
+        let data = load_sample("types");
+        let myint = &find_global(&data, "global_myint").entity_type;
+        assert_eq!(myint.name, "myint");
+        assert_eq!(myint.size, 4);
+
+        let const_int = &find_global(&data, "global_const").entity_type;
+        assert_eq!(const_int.name, "const int");
+        assert_eq!(const_int.size, 4);
+        let volatile_long = &find_global(&data, "global_volatile").entity_type;
+        assert_eq!(volatile_long.name, "volatile long int");
+        assert_eq!(volatile_long.size, 8);
+        assert_eq!(
+            find_global(&data, "global_str").entity_type.name,
+            "const char*"
+        );
+    }
+}
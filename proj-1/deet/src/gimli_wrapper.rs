@@ -10,12 +10,15 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{File, Function, Line, Location, Member, Type, Variable};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
+// Maximum number of DW_AT_abstract_origin/DW_AT_specification references to follow from a DIE
+const MAX_ORIGIN_DEPTH: usize = 8;
+
 pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
@@ -39,23 +42,30 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
+    // Define a mapping from type offsets (in .debug_info) to type structs
     let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
 
     let mut compilation_units: Vec<File> = Vec::new();
+    // The absolute path of each compilation unit's source file, used to match up line table rows
+    // (whose paths are built from the line program's directory table) with their File
+    let mut compilation_unit_paths: Vec<path::PathBuf> = Vec::new();
 
     // Iterate over the compilation units.
     let mut iter = dwarf.units();
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
+        let comp_dir = match unit.comp_dir {
+            Some(ref dir) => path::PathBuf::from(dir.to_string_lossy().as_ref()),
+            None => path::PathBuf::new(),
+        };
 
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
+            // Update the variable list for formal params/variables. (Types are resolved lazily
+            // by get_type, since a DIE can refer to a type that appears later in the unit.)
             match entry.tag() {
                 gimli::DW_TAG_compile_unit => {
                     let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
@@ -67,6 +77,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    compilation_unit_paths.push(resolve_path(&comp_dir, &name));
                     compilation_units.push(File {
                         name,
                         global_variables: Vec::new(),
@@ -74,33 +85,6 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
@@ -129,9 +113,38 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_type => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    func.return_type =
+                                        get_type(offset, &unit, &dwarf, &mut offset_to_type);
+                                }
+                            }
                             _ => {}
                         }
                     }
+                    // Out-of-line copies of inline functions and C++ methods defined outside of
+                    // their class get their name from the DIE they refer to
+                    if func.name.is_empty() {
+                        if let Some(DebugValue::Str(name)) =
+                            get_origin_attr(entry, gimli::DW_AT_name, &unit, &dwarf)
+                        {
+                            func.name = name;
+                        }
+                    }
+                    if func.line_number == 0 {
+                        if let Some(DebugValue::Uint(line_number)) =
+                            get_origin_attr(entry, gimli::DW_AT_decl_line, &unit, &dwarf)
+                        {
+                            func.line_number = line_number.try_into().unwrap();
+                        }
+                    }
+                    if func.return_type.is_none() {
+                        if let Some(DebugValue::Size(offset)) =
+                            get_origin_attr(entry, gimli::DW_AT_type, &unit, &dwarf)
+                        {
+                            func.return_type = get_type(offset, &unit, &dwarf, &mut offset_to_type);
+                        }
+                    }
                     compilation_units.last_mut().unwrap().functions.push(func);
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
@@ -151,15 +164,16 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
+                                    entity_type =
+                                        get_type(offset, &unit, &dwarf, &mut offset_to_type);
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                                // Keep variables whose location we can't make sense of (e.g. a
+                                // register, or a location list in optimized code), so that they
+                                // still show up, just without a value
+                                location =
+                                    Some(get_location(&attr, &unit).unwrap_or(Location::Unknown));
                             }
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
@@ -169,6 +183,29 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
+                    // Variables in out-of-line or inlined copies of a function only have a
+                    // location; everything else is in the DIE they refer to
+                    if name.is_empty() {
+                        if let Some(DebugValue::Str(origin_name)) =
+                            get_origin_attr(entry, gimli::DW_AT_name, &unit, &dwarf)
+                        {
+                            name = origin_name;
+                        }
+                    }
+                    if entity_type.is_none() {
+                        if let Some(DebugValue::Size(offset)) =
+                            get_origin_attr(entry, gimli::DW_AT_type, &unit, &dwarf)
+                        {
+                            entity_type = get_type(offset, &unit, &dwarf, &mut offset_to_type);
+                        }
+                    }
+                    if line_number == 0 {
+                        if let Some(DebugValue::Uint(num)) =
+                            get_origin_attr(entry, gimli::DW_AT_decl_line, &unit, &dwarf)
+                        {
+                            line_number = num;
+                        }
+                    }
                     if entity_type.is_some() && location.is_some() {
                         let var = Variable {
                             name,
@@ -221,9 +258,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     }
 
                     // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                    let path = resolve_path(&comp_dir, path.as_os_str().to_str().unwrap());
+                    let file = compilation_unit_paths
+                        .iter()
+                        .position(|unit_path| *unit_path == path)
+                        .map(|index| &mut compilation_units[index]);
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -243,6 +282,22 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     Ok(compilation_units)
 }
 
+/// Resolves a (possibly relative) source path against the compilation directory, and removes any
+/// "." and ".." components so that different spellings of the same path compare equal.
+fn resolve_path(comp_dir: &path::Path, file: &str) -> path::PathBuf {
+    let mut resolved = path::PathBuf::new();
+    for component in comp_dir.join(file).components() {
+        match component {
+            path::Component::CurDir => {}
+            path::Component::ParentDir => {
+                resolved.pop();
+            }
+            _ => resolved.push(component),
+        }
+    }
+    resolved
+}
+
 #[derive(Debug, Clone)]
 pub enum DebugValue {
     Str(String),
@@ -291,6 +346,239 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
+/// Returns the name of a DIE, if it has a DW_AT_name attribute.
+fn get_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the value of an unsigned integer attribute (e.g. DW_AT_byte_size) of a DIE.
+fn get_uint_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Option<u64> {
+    entry.attr(name).ok()??.udata_value()
+}
+
+/// Returns the .debug_info offset of the type referred to by a DIE's DW_AT_type attribute.
+fn get_type_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_type).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Returns the .debug_info offset of the DIE that a DIE's DW_AT_abstract_origin or
+/// DW_AT_specification attribute refers to.
+fn get_origin_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    let attr = match entry.attr(gimli::DW_AT_abstract_origin).ok()? {
+        Some(attr) => attr,
+        None => entry.attr(gimli::DW_AT_specification).ok()??,
+    };
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Follows a DIE's DW_AT_abstract_origin/DW_AT_specification references and returns the value of
+/// the given attribute from the first DIE along the way that has it. (For example, an out-of-line
+/// copy of an inline function refers to the abstract instance, which may in turn refer to a
+/// declaration.)
+fn get_origin_attr<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<DebugValue> {
+    let mut offset = get_origin_ref(entry, unit, dwarf)?;
+    // Guard against malformed DWARF with a cycle of references
+    for _ in 0..MAX_ORIGIN_DEPTH {
+        let unit_offset = UnitSectionOffset::DebugInfoOffset(gimli::DebugInfoOffset(offset))
+            .to_unit_offset(unit)?;
+        let origin = unit.entry(unit_offset).ok()?;
+        if let Some(attr) = origin.attr(name).ok()? {
+            return get_attr_value(&attr, unit, dwarf).ok();
+        }
+        offset = get_origin_ref(&origin, unit, dwarf)?;
+    }
+    None
+}
+
+/// Looks up the type DIE at the given .debug_info offset and converts it into a Type, caching the
+/// result in offset_to_type. Types are resolved on demand (rather than in the main DFS over the
+/// unit) because DWARF producers commonly emit a type after the DIEs that refer to it.
+fn get_type<R: Reader>(
+    offset: usize,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    offset_to_type: &mut HashMap<usize, Type>,
+) -> Option<Type> {
+    if let Some(entity_type) = offset_to_type.get(&offset) {
+        return Some(entity_type.clone());
+    }
+    let unit_offset =
+        UnitSectionOffset::DebugInfoOffset(gimli::DebugInfoOffset(offset)).to_unit_offset(unit)?;
+    let entry = unit.entry(unit_offset).ok()?;
+    let byte_size = get_uint_attr(&entry, gimli::DW_AT_byte_size);
+    let entity_type = match entry.tag() {
+        gimli::DW_TAG_base_type => Type::new(
+            get_name(&entry, unit, dwarf).unwrap_or_else(|| "<unknown>".to_string()),
+            // TODO: report error if the size is missing?
+            byte_size.unwrap_or(0).try_into().unwrap(),
+        ),
+        gimli::DW_TAG_pointer_type => {
+            // A pointer without a DW_AT_type is a void pointer
+            let pointed = match get_type_ref(&entry, unit, dwarf) {
+                Some(pointed_offset) => get_type(pointed_offset, unit, dwarf, offset_to_type)
+                    .map(|t| t.name)
+                    .unwrap_or_else(|| "<unknown>".to_string()),
+                None => "void".to_string(),
+            };
+            Type::new(
+                format!("{}*", pointed),
+                byte_size
+                    .map(|size| size.try_into().unwrap())
+                    .unwrap_or_else(|| unit.encoding().address_size.into()),
+            )
+        }
+        gimli::DW_TAG_structure_type => {
+            let name = format!(
+                "struct {}",
+                get_name(&entry, unit, dwarf).unwrap_or_else(|| "<anonymous>".to_string())
+            );
+            // Register the struct before resolving its members, so that a self-referential struct
+            // (e.g. a linked list node with a pointer to the next node) doesn't recurse forever
+            let mut entity_type = Type::new(name, byte_size.unwrap_or(0).try_into().unwrap());
+            offset_to_type.insert(offset, entity_type.clone());
+            match get_members(unit_offset, unit, dwarf, offset_to_type) {
+                Some(members) => entity_type.members = members,
+                None => {
+                    // Don't leave the memberless stub behind for later lookups to pick up
+                    offset_to_type.remove(&offset);
+                    return None;
+                }
+            }
+            entity_type
+        }
+        gimli::DW_TAG_array_type => {
+            let element_offset = get_type_ref(&entry, unit, dwarf)?;
+            let element_type = get_type(element_offset, unit, dwarf, offset_to_type)?;
+            // Each dimension of the array is described by a DW_TAG_subrange_type child. For
+            // multidimensional arrays, build the type from the innermost dimension outwards, so
+            // that e.g. int[2][3] is an array of 2 int[3]s.
+            let mut dimensions = get_subrange_counts(unit_offset, unit)?;
+            dimensions.reverse();
+            let mut entity_type = element_type;
+            for count in dimensions {
+                entity_type = Type::new_array(entity_type, count);
+            }
+            entity_type
+        }
+        gimli::DW_TAG_typedef => {
+            // A typedef has the same layout as its underlying type, but keeps its own name
+            let underlying_offset = get_type_ref(&entry, unit, dwarf)?;
+            let mut entity_type = get_type(underlying_offset, unit, dwarf, offset_to_type)?;
+            if let Some(name) = get_name(&entry, unit, dwarf) {
+                entity_type.name = name;
+            }
+            entity_type
+        }
+        gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => {
+            let qualifier = if entry.tag() == gimli::DW_TAG_const_type {
+                "const"
+            } else {
+                "volatile"
+            };
+            // As with pointers, a qualifier without a DW_AT_type applies to void
+            match get_type_ref(&entry, unit, dwarf) {
+                Some(qualified_offset) => {
+                    let mut entity_type = get_type(qualified_offset, unit, dwarf, offset_to_type)?;
+                    entity_type.name = format!("{} {}", qualifier, entity_type.name);
+                    entity_type
+                }
+                None => Type::new(format!("{} void", qualifier), 0),
+            }
+        }
+        _ => return None,
+    };
+    offset_to_type.insert(offset, entity_type.clone());
+    Some(entity_type)
+}
+
+/// Returns the element count of each DW_TAG_subrange_type child of the array DIE at the given
+/// offset. Arrays without a known bound (e.g. flexible array members) have a count of 0.
+fn get_subrange_counts<R: Reader>(
+    array_offset: UnitOffset,
+    unit: &gimli::Unit<R>,
+) -> Option<Vec<usize>> {
+    let mut counts = Vec::new();
+    let mut tree = unit.entries_tree(Some(array_offset)).ok()?;
+    let mut children = tree.root().ok()?.children();
+    while let Some(child) = children.next().ok()? {
+        let entry = child.entry();
+        if entry.tag() != gimli::DW_TAG_subrange_type {
+            continue;
+        }
+        let count = if let Some(count) = get_uint_attr(entry, gimli::DW_AT_count) {
+            count
+        } else if let Some(upper_bound) = get_uint_attr(entry, gimli::DW_AT_upper_bound) {
+            upper_bound + 1
+        } else {
+            0
+        };
+        counts.push(count.try_into().unwrap());
+    }
+    Some(counts)
+}
+
+/// Returns the DW_TAG_member children of the struct DIE at the given offset.
+fn get_members<R: Reader>(
+    struct_offset: UnitOffset,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    offset_to_type: &mut HashMap<usize, Type>,
+) -> Option<Vec<Member>> {
+    let mut members = Vec::new();
+    let mut tree = unit.entries_tree(Some(struct_offset)).ok()?;
+    let mut children = tree.root().ok()?.children();
+    while let Some(child) = children.next().ok()? {
+        let entry = child.entry();
+        if entry.tag() != gimli::DW_TAG_member {
+            continue;
+        }
+        let entity_type = match get_type_ref(entry, unit, dwarf)
+            .and_then(|type_offset| get_type(type_offset, unit, dwarf, offset_to_type))
+        {
+            Some(entity_type) => entity_type,
+            None => continue,
+        };
+        members.push(Member {
+            name: get_name(entry, unit, dwarf).unwrap_or_default(),
+            entity_type,
+            offset: get_uint_attr(entry, gimli::DW_AT_data_member_location)
+                .unwrap_or(0)
+                .try_into()
+                .unwrap(),
+        });
+    }
+    Some(members)
+}
+
 fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
@@ -344,6 +632,16 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        // DWARF5 keeps file names in .debug_line_str, and (with some compilers) refers to strings
+        // and addresses by index into .debug_str_offsets and .debug_addr
+        gimli::AttributeValue::DebugLineStrRef(_)
+        | gimli::AttributeValue::DebugStrOffsetsIndex(_) => {
+            let s = dwarf.attr_string(unit, value)?;
+            Ok(DebugValue::Str(s.to_string_lossy()?.to_string()))
+        }
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Ok(DebugValue::Uint(dwarf.address(unit, index)?))
+        }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
@@ -368,13 +666,15 @@ fn dump_file_index<R: Reader, W: Write>(
     unit: &gimli::Unit<R>,
     dwarf: &gimli::Dwarf<R>,
 ) -> Result<(), Error> {
-    if file == 0 {
-        return Ok(());
-    }
     let header = match unit.line_program {
         Some(ref program) => program.header(),
         None => return Ok(()),
     };
+    // Before DWARF5, file indices are 1-based and 0 means "no file"; DWARF5 has an explicit
+    // file 0 (the primary source file)
+    if file == 0 && header.version() <= 4 {
+        return Ok(());
+    }
     let file = match header.file(file) {
         Some(header) => header,
         None => {
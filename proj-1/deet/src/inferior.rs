@@ -1,8 +1,17 @@
+use crate::dwarf_data::{DwarfData, Line, Location};
+use nix::libc::user_regs_struct;
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::process::Child;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::mem::size_of;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+const INT3: u8 = 0xcc;
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -26,20 +35,38 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+fn align_addr_to_word(addr: usize) -> usize {
+    addr & (-(size_of::<usize>() as isize) as usize)
+}
+
 pub struct Inferior {
     child: Child,
+    /// Maps the address of each installed breakpoint to the original byte that the INT3 replaced
+    breakpoints: HashMap<usize, u8>,
+    /// The signal that the inferior last stopped with, if it should be delivered when the
+    /// inferior resumes (i.e. anything but a SIGTRAP from the debugger)
+    pending_signal: Cell<Option<signal::Signal>>,
 }
 
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
+    /// an error is encountered. The inferior is stopped at the first instruction of the program.
     pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
-        // TODO: implement me!
-        println!(
-            "Inferior::new not implemented! target={}, args={:?}",
-            target, args
-        );
-        None
+        let mut cmd = Command::new(target);
+        cmd.args(args);
+        unsafe {
+            cmd.pre_exec(child_traceme);
+        }
+        let inferior = Inferior {
+            child: cmd.spawn().ok()?,
+            breakpoints: HashMap::new(),
+            pending_signal: Cell::new(None),
+        };
+        // PTRACE_TRACEME makes the child stop with a SIGTRAP once it calls exec
+        match inferior.wait(None).ok()? {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => Some(inferior),
+            _ => None,
+        }
     }
 
     /// Returns the pid of this inferior.
@@ -47,13 +74,309 @@ impl Inferior {
         nix::unistd::Pid::from_raw(self.child.id() as i32)
     }
 
+    /// Returns the address that the executable was loaded at, i.e. the start of its first mapping
+    /// in /proc/{pid}/maps. For a position-independent executable, this is randomized on each run.
+    pub fn load_address(&self) -> Option<usize> {
+        let exe = fs::read_link(format!("/proc/{}/exe", self.pid())).ok()?;
+        let maps = fs::read_to_string(format!("/proc/{}/maps", self.pid())).ok()?;
+        parse_load_address(&maps, exe.to_str()?)
+    }
+
+    /// Kills the inferior and reaps it, so that it doesn't linger as a zombie.
+    pub fn kill(&mut self) {
+        if ptrace::kill(self.pid()).is_ok() {
+            let _ = waitpid(self.pid(), None);
+        }
+    }
+
+    /// Writes a byte to the inferior's memory, returning the byte that was there before.
+    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        let aligned_addr = align_addr_to_word(addr);
+        let byte_offset = addr - aligned_addr;
+        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
+        ptrace::write(
+            self.pid(),
+            aligned_addr as ptrace::AddressType,
+            updated_word as *mut std::ffi::c_void,
+        )?;
+        Ok(orig_byte as u8)
+    }
+
+    /// Installs a breakpoint by replacing the first byte of the instruction at addr with an INT3.
+    pub fn install_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if self.breakpoints.contains_key(&addr) {
+            return Ok(());
+        }
+        let orig_byte = self.write_byte(addr, INT3)?;
+        self.breakpoints.insert(addr, orig_byte);
+        Ok(())
+    }
+
+    /// Wakes up the inferior and waits until it stops or terminates. If the inferior is stopped at
+    /// a breakpoint, the original instruction is executed first, and the breakpoint is re-armed
+    /// so that it will trigger again next time.
+    pub fn cont(&mut self) -> Result<Status, nix::Error> {
+        if self.breakpoints.contains_key(&self.get_rip()?) {
+            match self.step_instruction()? {
+                Status::Stopped(signal::Signal::SIGTRAP, _) => {}
+                other => return Ok(other),
+            }
+        }
+        // Deliver the signal that stopped the inferior, if any (e.g. after a segfault,
+        // continuing lets the inferior's handler run, or the default action kill it)
+        ptrace::cont(self.pid(), self.pending_signal.take())?;
+        self.wait(None)
+    }
+
+    /// Executes a single machine instruction. If the inferior is stopped at a breakpoint, the
+    /// original instruction is temporarily restored so that it can be executed, and the INT3 is
+    /// put back afterwards.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        let rip = self.get_rip()?;
+        let orig_byte = self.breakpoints.get(&rip).copied();
+        if let Some(orig_byte) = orig_byte {
+            self.write_byte(rip, orig_byte)?;
+        }
+        ptrace::step(self.pid(), None)?;
+        // Don't use self.wait here: the instruction we just stepped over may be one byte long,
+        // in which case rip - 1 is the breakpoint again, but we didn't hit it
+        let status = self.wait_status(None)?;
+        if let (Some(_), Status::Stopped(..)) = (orig_byte, &status) {
+            self.write_byte(rip, INT3)?;
+        }
+        Ok(status)
+    }
+
+    /// Runs until the next source line (according to debug_data) is reached. If step_over_calls
+    /// is true, function calls on the current line are executed in their entirety (like gdb's
+    /// "next"); otherwise, we stop at the first line of the called function (like "step").
+    /// Functions that we have no line information for (e.g. library functions) are always stepped
+    /// over.
+    pub fn step_line(
+        &mut self,
+        debug_data: &DwarfData,
+        step_over_calls: bool,
+    ) -> Result<Status, nix::Error> {
+        let start_line = line_key(debug_data.get_line_from_addr(self.get_rip()?));
+        loop {
+            let regs = ptrace::getregs(self.pid())?;
+            let is_call = self.is_call_instruction(regs.rip as usize)?;
+            let mut status = self.step_instruction()?;
+            if is_call {
+                if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+                    if step_over_calls || debug_data.get_line_from_addr(rip).is_none() {
+                        // The call pushed the return address onto the stack
+                        let return_addr =
+                            self.read_word(ptrace::getregs(self.pid())?.rsp as usize)?;
+                        status = self.run_until_return(return_addr as usize, regs.rsp as usize)?;
+                    }
+                }
+            }
+            let rip = match status {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => rip,
+                other => return Ok(other),
+            };
+            if self.breakpoints.contains_key(&rip) {
+                // Stepping never skips past a breakpoint
+                return Ok(status);
+            }
+            match debug_data.get_line_from_addr(rip) {
+                Some(line) => {
+                    if line_key(Some(line)) != start_line {
+                        return Ok(status);
+                    }
+                }
+                // We returned from the outermost function we have debugging symbols for (e.g.
+                // main returned into libc), so there are no more lines to step through
+                None => return self.cont(),
+            }
+        }
+    }
+
+    /// Runs until the current function returns to its caller, like gdb's "finish". The return
+    /// address is normally at [rbp+8], but rbp still belongs to the caller until the function's
+    /// prologue has run (and again once its epilogue has), so those cases are handled specially.
+    pub fn finish(&mut self) -> Result<Status, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let (rsp, rbp) = (regs.rsp as usize, regs.rbp as usize);
+        let mut code = self.read_memory(regs.rip as usize, 4)?;
+        // Functions built with -fcf-protection start with endbr64
+        if code == [0xf3, 0x0f, 0x1e, 0xfa] {
+            code = self.read_memory(regs.rip as usize + 4, 4)?;
+        }
+        // Where the return address is stored, and thus what rsp will be after returning
+        let return_addr_slot = match code[..] {
+            // push %rbp, or ret: the return address is on top of the stack
+            [0x55, ..] | [0xc3, ..] => rsp,
+            // mov %rsp,%rbp: rbp has been pushed on top of the return address
+            [0x48, 0x89, 0xe5, _] => rsp + 8,
+            _ => rbp + 8,
+        };
+        let return_addr = self.read_word(return_addr_slot)? as usize;
+        self.run_until_return(return_addr, return_addr_slot + 8)
+    }
+
+    /// Runs until the function that is executing returns to return_addr. rsp_after_return is the
+    /// stack pointer once it has returned, which tells a return from this call apart from returns
+    /// from recursive calls to the same function.
+    fn run_until_return(
+        &mut self,
+        return_addr: usize,
+        rsp_after_return: usize,
+    ) -> Result<Status, nix::Error> {
+        let temporary = !self.breakpoints.contains_key(&return_addr);
+        if temporary {
+            self.install_breakpoint(return_addr)?;
+        }
+        let status = loop {
+            match self.cont()? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == return_addr => {
+                    if ptrace::getregs(self.pid())?.rsp as usize >= rsp_after_return {
+                        break Status::Stopped(signal::Signal::SIGTRAP, rip);
+                    }
+                }
+                other => break other,
+            }
+        };
+        if temporary {
+            if let Status::Stopped(..) = status {
+                self.remove_breakpoint(return_addr)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Returns true if the instruction at addr is a call (either a direct call, or an indirect
+    /// call through a register or memory, e.g. a function pointer).
+    fn is_call_instruction(&self, addr: usize) -> Result<bool, nix::Error> {
+        let bytes = self.read_memory(addr, 3)?;
+        // Skip a REX prefix (e.g. call *%r8 is 41 ff d0)
+        let opcode = if (0x40..=0x4f).contains(&bytes[0]) {
+            &bytes[1..]
+        } else {
+            &bytes[..]
+        };
+        // 0xe8 is call rel32. 0xff is a group of instructions; the reg field of the ModRM byte that
+        // follows is 2 for an indirect call
+        Ok(opcode[0] == 0xe8 || (opcode[0] == 0xff && (opcode[1] >> 3) & 7 == 2))
+    }
+
+    /// Returns the instruction pointer of each stack frame, starting with the current one, by
+    /// following the chain of saved frame pointers (each frame's rbp points to the caller's saved
+    /// rbp, with the return address just above it). Stops after main, or if the chain ends or
+    /// leads somewhere unreadable. This relies on the inferior being compiled with frame pointers,
+    /// and misses the caller's frame while the current function is still in its prologue.
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<usize>, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let mut rip = regs.rip as usize;
+        let mut rbp = regs.rbp as usize;
+        let mut frames = Vec::new();
+        loop {
+            frames.push(rip);
+            if debug_data.get_function_from_addr(rip).as_deref() == Some("main") || rbp == 0 {
+                break;
+            }
+            rip = match self.read_word(rbp + 8) {
+                Ok(return_addr) => return_addr as usize,
+                Err(_) => break,
+            };
+            rbp = match self.read_word(rbp) {
+                Ok(saved_rbp) => saved_rbp as usize,
+                Err(_) => break,
+            };
+        }
+        Ok(frames)
+    }
+
+    /// Reads len bytes of the inferior's memory starting at addr. Any breakpoints in that range
+    /// are hidden, i.e. the original bytes are returned instead of INT3s.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = addr;
+        while bytes.len() < len {
+            let word = self.read_word(word_addr)?.to_le_bytes();
+            let remaining = len - bytes.len();
+            bytes.extend_from_slice(&word[..remaining.min(word.len())]);
+            word_addr += word.len();
+        }
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            if let Some(orig_byte) = self.breakpoints.get(&(addr + i)) {
+                *byte = *orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the inferior's general-purpose registers.
+    pub fn get_registers(&self) -> Result<user_regs_struct, nix::Error> {
+        ptrace::getregs(self.pid())
+    }
+
+    /// Returns the address of a variable in the current stack frame (or of a global). load_bias
+    /// is the executable's load bias (see DwarfData::load_bias), which applies to globals.
+    pub fn variable_address(
+        &self,
+        location: &Location,
+        load_bias: usize,
+    ) -> Result<usize, nix::Error> {
+        match location {
+            Location::Address(addr) => Ok(addr + load_bias),
+            Location::FramePointerOffset(offset) => {
+                // Locals are at an offset from the frame base, which gcc sets to the canonical
+                // frame address: the value of rsp before the call instruction pushed the return
+                // address. With frame pointers, that's rbp + 16, just above the return address
+                // and the caller's saved rbp.
+                let rbp = ptrace::getregs(self.pid())?.rbp as usize;
+                Ok((rbp as isize + 16 + offset) as usize)
+            }
+            Location::Unknown => Err(nix::Error::UnsupportedOperation),
+        }
+    }
+
+    /// Removes a breakpoint, restoring the original byte of the instruction.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.breakpoints.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
+    fn get_rip(&self) -> Result<usize, nix::Error> {
+        Ok(ptrace::getregs(self.pid())?.rip as usize)
+    }
+
+    fn read_word(&self, addr: usize) -> Result<u64, nix::Error> {
+        Ok(ptrace::read(self.pid(), addr as ptrace::AddressType)? as u64)
+    }
+
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
+    /// after the waitpid call. If the inferior stopped because it hit one of our breakpoints, the
+    /// instruction pointer is moved back to the start of the breakpoint's instruction (it points
+    /// just past the INT3 after the trap), so the Status contains the breakpoint's address.
     pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let status = self.wait_status(options)?;
+        if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+            if self.breakpoints.contains_key(&(rip - 1)) {
+                let mut regs = ptrace::getregs(self.pid())?;
+                regs.rip = (rip - 1) as u64;
+                ptrace::setregs(self.pid(), regs)?;
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1));
+            }
+        }
+        Ok(status)
+    }
+
+    fn wait_status(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
         Ok(match waitpid(self.pid(), options)? {
             WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
             WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
             WaitStatus::Stopped(_pid, signal) => {
+                if signal != signal::Signal::SIGTRAP {
+                    self.pending_signal.set(Some(signal));
+                }
                 let regs = ptrace::getregs(self.pid())?;
                 Status::Stopped(signal, regs.rip as usize)
             }
@@ -61,3 +384,196 @@ impl Inferior {
         })
     }
 }
+
+/// Identifies a source line for the purpose of telling whether we've moved on to a different
+/// line (the address within the line doesn't matter).
+fn line_key(line: Option<Line>) -> Option<(String, usize)> {
+    line.map(|line| (line.file, line.number))
+}
+
+/// Finds the start address of the first mapping of the given executable in the contents of a
+/// /proc/{pid}/maps file. Each line looks like:
+///
+/// ```text
+/// 555555554000-555555555000 r--p 00000000 08:01 1234    /path/to/exe
+/// ```
+fn parse_load_address(maps: &str, exe: &str) -> Option<usize> {
+    maps.lines()
+        .find(|line| line.split_whitespace().nth(5) == Some(exe))
+        .and_then(|line| line.split('-').next())
+        .and_then(|start| usize::from_str_radix(start, 16).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_load_address() {
+        let maps = "\
+555555554000-555555555000 r--p 00000000 08:01 1234                       /tmp/hello
+555555555000-555555556000 r-xp 00001000 08:01 1234                       /tmp/hello
+7ffff7dd3000-7ffff7dfc000 r--p 00000000 08:01 5678                       /usr/lib/libc.so.6
+7ffffffde000-7ffffffff000 rw-p 00000000 00:00 0                          [stack]
+";
+        assert_eq!(parse_load_address(maps, "/tmp/hello"), Some(0x555555554000));
+        assert_eq!(
+            parse_load_address(maps, "/usr/lib/libc.so.6"),
+            Some(0x7ffff7dd3000)
+        );
+        assert_eq!(parse_load_address(maps, "/tmp/other"), None);
+    }
+
+    #[test]
+    fn test_args() {
+        let args = vec!["hello".to_string(), "two words".to_string()];
+        let mut inferior = Inferior::new("samples/echo_args", &args).unwrap();
+        let cmdline = fs::read(format!("/proc/{}/cmdline", inferior.pid())).unwrap();
+        assert_eq!(cmdline, b"samples/echo_args\0hello\0two words\0");
+        // echo_args exits with argc
+        assert!(matches!(inferior.cont().unwrap(), Status::Exited(3)));
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let debug_data = DwarfData::from_file("samples/count")
+            .expect("Could not load samples/count. Have you run make?");
+        let line_addr = debug_data.get_addr_for_line(Some("count.c"), 5).unwrap();
+        let mut inferior = Inferior::new("samples/count", &Vec::new()).unwrap();
+        inferior.install_breakpoint(line_addr).unwrap();
+        match inferior.cont().unwrap() {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) => assert_eq!(rip, line_addr),
+            _ => panic!("Expected the inferior to stop at the breakpoint"),
+        }
+        // Continuing steps over the breakpoint and runs to the end
+        match inferior.cont().unwrap() {
+            Status::Exited(0) => {}
+            _ => panic!("Expected the inferior to exit normally"),
+        }
+    }
+
+    /// Starts samples/function_calls and runs it to the breakpoint at the given line.
+    fn run_function_calls_to_line(line: usize) -> (DwarfData, Inferior) {
+        let debug_data = DwarfData::from_file("samples/function_calls")
+            .expect("Could not load samples/function_calls. Have you run make?");
+        let mut inferior = Inferior::new("samples/function_calls", &Vec::new()).unwrap();
+        let addr = debug_data
+            .get_addr_for_line(Some("function_calls.c"), line)
+            .unwrap();
+        inferior.install_breakpoint(addr).unwrap();
+        assert!(matches!(
+            inferior.cont().unwrap(),
+            Status::Stopped(signal::Signal::SIGTRAP, _)
+        ));
+        (debug_data, inferior)
+    }
+
+    fn current_line(debug_data: &DwarfData, inferior: &Inferior) -> usize {
+        let rip = inferior.get_rip().unwrap();
+        debug_data.get_line_from_addr(rip).unwrap().number
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        let (debug_data, mut inferior) = run_function_calls_to_line(17);
+        let rip = inferior.get_rip().unwrap();
+        match inferior.step_instruction().unwrap() {
+            Status::Stopped(signal::Signal::SIGTRAP, new_rip) => {
+                assert!(new_rip > rip);
+                assert_eq!(new_rip, inferior.get_rip().unwrap());
+            }
+            _ => panic!("Expected the inferior to stop after one instruction"),
+        }
+        // Line 17 takes more than one instruction
+        assert_eq!(current_line(&debug_data, &inferior), 17);
+        inferior.kill();
+    }
+
+    #[test]
+    fn test_step_and_next() {
+        let (debug_data, mut inferior) = run_function_calls_to_line(17);
+        // next steps over the call to printf on line 17 (and over func2 on line 18)
+        inferior.step_line(&debug_data, true).unwrap();
+        assert_eq!(current_line(&debug_data, &inferior), 18);
+        inferior.step_line(&debug_data, true).unwrap();
+        assert_eq!(current_line(&debug_data, &inferior), 19);
+        // step goes into func3, stopping at the line it's declared on (its prologue)
+        inferior.step_line(&debug_data, false).unwrap();
+        let rip = inferior.get_rip().unwrap();
+        assert_eq!(debug_data.get_function_from_addr(rip).unwrap(), "func3");
+        assert_eq!(current_line(&debug_data, &inferior), 5);
+        // step doesn't go into printf, since we have no line info for it
+        inferior.step_line(&debug_data, false).unwrap();
+        assert_eq!(current_line(&debug_data, &inferior), 6);
+        inferior.step_line(&debug_data, false).unwrap();
+        assert_eq!(current_line(&debug_data, &inferior), 7);
+        // Stepping off the end of func3 returns to the caller
+        inferior.step_line(&debug_data, false).unwrap();
+        let rip = inferior.get_rip().unwrap();
+        assert_eq!(debug_data.get_function_from_addr(rip).unwrap(), "func1");
+        assert_eq!(current_line(&debug_data, &inferior), 20);
+        inferior.kill();
+    }
+
+    #[test]
+    fn test_backtrace() {
+        // The first time line 6 is reached, func3 was called by func2, which was called by func1
+        let (debug_data, inferior) = run_function_calls_to_line(6);
+        let functions: Vec<String> = inferior
+            .backtrace(&debug_data)
+            .unwrap()
+            .iter()
+            .map(|rip| debug_data.get_function_from_addr(*rip).unwrap())
+            .collect();
+        assert_eq!(functions, vec!["func3", "func2", "func1", "main"]);
+        let mut inferior = inferior;
+        inferior.kill();
+    }
+
+    #[test]
+    fn test_segfault() {
+        let debug_data = DwarfData::from_file("samples/segfault")
+            .expect("Could not load samples/segfault. Have you run make?");
+        let mut inferior = Inferior::new("samples/segfault", &Vec::new()).unwrap();
+        let rip = match inferior.cont().unwrap() {
+            Status::Stopped(signal::Signal::SIGSEGV, rip) => rip,
+            _ => panic!("Expected the inferior to stop with SIGSEGV"),
+        };
+        assert_eq!(debug_data.get_line_from_addr(rip).unwrap().number, 5);
+        let functions: Vec<String> = inferior
+            .backtrace(&debug_data)
+            .unwrap()
+            .iter()
+            .map(|rip| debug_data.get_function_from_addr(*rip).unwrap())
+            .collect();
+        assert_eq!(functions, vec!["func2", "func1", "main"]);
+        // Continuing delivers the SIGSEGV, which kills the inferior
+        assert!(matches!(
+            inferior.cont().unwrap(),
+            Status::Signaled(signal::Signal::SIGSEGV)
+        ));
+    }
+
+    #[test]
+    fn test_breakpoint_in_loop() {
+        // A breakpoint that is hit repeatedly must be re-armed each time we continue past it.
+        // Put it at the start of func3 (called twice), which is a 1-byte push instruction.
+        let debug_data = DwarfData::from_file("samples/function_calls")
+            .expect("Could not load samples/function_calls. Have you run make?");
+        let func_addr = debug_data.get_addr_for_function(None, "func3").unwrap();
+        let mut inferior = Inferior::new("samples/function_calls", &Vec::new()).unwrap();
+        inferior.install_breakpoint(func_addr).unwrap();
+        let mut hits = 0;
+        loop {
+            match inferior.cont().unwrap() {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                    assert_eq!(rip, func_addr);
+                    hits += 1;
+                }
+                Status::Exited(0) => break,
+                _ => panic!("Unexpected status"),
+            }
+        }
+        assert_eq!(hits, 2);
+    }
+}
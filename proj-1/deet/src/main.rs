@@ -1,5 +1,7 @@
 mod debugger;
 mod debugger_command;
+mod dwarf_data;
+mod gimli_wrapper;
 mod inferior;
 
 use crate::debugger::Debugger;
@@ -8,15 +10,17 @@ use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+    if args.len() < 2 {
+        println!("Usage: {} <target program> [args...]", args[0]);
         std::process::exit(1);
     }
     let target = &args[1];
+    // Any further arguments are passed to the target program when it is run
+    let target_args = args[2..].to_vec();
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    Debugger::new(target, target_args).run();
 }
@@ -0,0 +1,76 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// A file recording every request we answer, one line each, in the "combined" log format that
+/// Apache and nginx use (so that the usual log analysis tools can read it), with the request's
+/// X-Request-Id added on the end. Lines are buffered, so they only show up in the file once flush
+/// is called (or the buffer fills up).
+pub struct AccessLog {
+    writer: Mutex<BufWriter<File>>,
+}
+
+/// Returns the value of a request header as a quoted log field, or "-" if it isn't there
+fn quoted_header(request: &http::Request<Vec<u8>>, name: &str) -> String {
+    match request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "-".to_string(),
+    }
+}
+
+impl AccessLog {
+    /// Opens the log file at path, adding on to the end of it if it already exists
+    pub fn open(path: &str) -> Result<AccessLog, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("can't open {}: {}", path, err))?;
+        Ok(AccessLog {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Records that we answered a request from client_ip with a response with a body_bytes-long
+    /// body
+    pub fn record(
+        &self,
+        client_ip: IpAddr,
+        request: &http::Request<Vec<u8>>,
+        response: &http::Response<Vec<u8>>,
+        body_bytes: usize,
+    ) {
+        let line = format!(
+            "{} - - [{}] \"{} {} {:?}\" {} {} {} {} {}\n",
+            client_ip,
+            chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z"),
+            request.method(),
+            request.uri(),
+            request.version(),
+            response.status().as_str(),
+            // Like Apache, write "-" rather than 0 for an empty body
+            match body_bytes {
+                0 => "-".to_string(),
+                bytes => bytes.to_string(),
+            },
+            quoted_header(request, "referer"),
+            quoted_header(request, "user-agent"),
+            quoted_header(request, "x-request-id"),
+        );
+        if let Err(err) = self.writer.lock().unwrap().write_all(line.as_bytes()) {
+            log::warn!("Failed to write to access log: {}", err);
+        }
+    }
+
+    /// Writes out any lines that are still sitting in the buffer
+    pub fn flush(&self) {
+        if let Err(err) = self.writer.lock().unwrap().flush() {
+            log::warn!("Failed to write to access log: {}", err);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::cmp::min;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// How much of a body we hold in memory at once while forwarding it
+pub const COPY_BUFFER_SIZE: usize = 8192;
+
+/// Longest chunk size (or trailer) line we accept in a chunked body
+const MAX_CHUNK_LINE_SIZE: usize = 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The sender hung up before sending the whole body. IncompleteBody contains the number of
+    /// bytes that were copied before it hung up
+    IncompleteBody(usize),
+    /// Encountered an I/O error when reading the body from the sender
+    ReadFailed(std::io::Error),
+    /// Encountered an I/O error when writing the body to the receiver
+    WriteFailed(std::io::Error),
+}
+
+/// Copies the next `length` bytes of a body from `reader` to `writer`, COPY_BUFFER_SIZE bytes at a
+/// time, so that a large body can be forwarded without ever holding the whole thing in memory.
+/// Nothing past `length` is read, since that belongs to the next request or response on the
+/// connection.
+pub async fn copy_body<R, W>(reader: &mut R, writer: &mut W, length: usize) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = [0_u8; COPY_BUFFER_SIZE];
+    let mut bytes_copied = 0;
+    while bytes_copied < length {
+        let bytes_read = reader
+            .read(&mut buffer[..min(COPY_BUFFER_SIZE, length - bytes_copied)])
+            .await
+            .map_err(Error::ReadFailed)?;
+        if bytes_read == 0 {
+            return Err(Error::IncompleteBody(bytes_copied));
+        }
+        writer
+            .write_all(&buffer[..bytes_read])
+            .await
+            .map_err(Error::WriteFailed)?;
+        bytes_copied += bytes_read;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ChunkedError {
+    /// The sender hung up before sending the whole body. IncompleteBody contains the number of
+    /// bytes that were decoded before it hung up
+    IncompleteBody(usize),
+    /// A chunk size line isn't a valid hex number (or a chunk isn't followed by \r\n)
+    InvalidChunkSize,
+    /// The decoded body is bigger than the maximum body size
+    BodyTooLarge,
+    /// Encountered an I/O error when reading the body from the sender
+    ReadFailed(std::io::Error),
+}
+
+/// Returns true if a body is sent using chunked transfer encoding. (Chunked has to be the last
+/// encoding applied, so we only need to check the end of the header.)
+pub fn is_chunked(headers: &http::HeaderMap) -> bool {
+    headers
+        .get_all("transfer-encoding")
+        .iter()
+        .next_back()
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .rsplit(',')
+                .next()
+                .unwrap()
+                .trim()
+                .eq_ignore_ascii_case("chunked")
+        })
+}
+
+/// Reads more bytes from the stream onto the end of buffer, returning IncompleteBody if the sender
+/// hangs up before sending anything.
+async fn read_more<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+    bytes_decoded: usize,
+) -> Result<(), ChunkedError> {
+    let mut chunk = [0_u8; 512];
+    let bytes_read = stream
+        .read(&mut chunk)
+        .await
+        .map_err(ChunkedError::ReadFailed)?;
+    if bytes_read == 0 {
+        log::debug!("Sender hung up in the middle of a chunked body");
+        return Err(ChunkedError::IncompleteBody(bytes_decoded));
+    }
+    buffer.extend_from_slice(&chunk[..bytes_read]);
+    Ok(())
+}
+
+/// Removes a \r\n-terminated line from the front of buffer (reading more from the stream as
+/// needed), and returns it without the \r\n.
+async fn read_chunk_line<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+    bytes_decoded: usize,
+) -> Result<Vec<u8>, ChunkedError> {
+    loop {
+        if let Some(line_len) = buffer.windows(2).position(|window| window == b"\r\n") {
+            let line = buffer[..line_len].to_vec();
+            buffer.drain(..line_len + 2);
+            return Ok(line);
+        }
+        if buffer.len() > MAX_CHUNK_LINE_SIZE {
+            return Err(ChunkedError::InvalidChunkSize);
+        }
+        read_more(stream, buffer, bytes_decoded).await?;
+    }
+}
+
+/// Parses the size of a chunk from its size line, ignoring any chunk extensions
+/// (e.g. "1a;name=value").
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ChunkedError> {
+    let line = std::str::from_utf8(line).or(Err(ChunkedError::InvalidChunkSize))?;
+    let size = line.split(';').next().unwrap().trim();
+    if size.is_empty() || !size.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ChunkedError::InvalidChunkSize);
+    }
+    usize::from_str_radix(size, 16).or(Err(ChunkedError::InvalidChunkSize))
+}
+
+/// Reads a body sent with chunked transfer encoding: a series of chunks, each preceded by its size
+/// in hex on its own line, ending with a zero-sized chunk (and optional trailers). On the way in,
+/// `body` holds whatever part of the encoded body was read along with the headers; on the way
+/// out, it holds the decoded body.
+pub async fn read_chunked_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    body: &mut Vec<u8>,
+    max_size: usize,
+) -> Result<(), ChunkedError> {
+    let mut buffer = std::mem::take(body);
+    loop {
+        let size = parse_chunk_size(&read_chunk_line(stream, &mut buffer, body.len()).await?)?;
+        if size == 0 {
+            break;
+        }
+        // (Written this way around so that a huge size can't overflow the sum)
+        if size > max_size - body.len() {
+            return Err(ChunkedError::BodyTooLarge);
+        }
+        // Read the chunk data, plus the \r\n that follows it
+        while buffer.len() < size + 2 {
+            read_more(stream, &mut buffer, body.len()).await?;
+        }
+        if &buffer[size..size + 2] != b"\r\n" {
+            return Err(ChunkedError::InvalidChunkSize);
+        }
+        body.extend_from_slice(&buffer[..size]);
+        buffer.drain(..size + 2);
+    }
+    // Skip over any trailers, up to the blank line that ends the body
+    while !read_chunk_line(stream, &mut buffer, body.len())
+        .await?
+        .is_empty()
+    {}
+    Ok(())
+}
+
+/// AsyncWrite implementations for testing the code that writes requests, responses and bodies out
+#[cfg(test)]
+pub mod test_writers {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+
+    /// Accepts at most a few bytes per write, like a socket whose send buffer is nearly full
+    pub struct TrickleWriter {
+        pub written: Vec<u8>,
+    }
+
+    impl AsyncWrite for TrickleWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let len = std::cmp::min(buf.len(), 3);
+            self.written.extend_from_slice(&buf[..len]);
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Accepts everything it's given, keeping count of how many writes it took
+    pub struct CountingWriter {
+        pub written: Vec<u8>,
+        pub writes: usize,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.written.extend_from_slice(buf);
+            self.writes += 1;
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::test_writers::CountingWriter;
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Produces `remaining` bytes of data, keeping track of the biggest read anyone asked for
+    struct CountingReader {
+        remaining: usize,
+        largest_read: usize,
+    }
+
+    impl AsyncRead for CountingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.largest_read = std::cmp::max(self.largest_read, buf.len());
+            let len = min(buf.len(), self.remaining);
+            for byte in &mut buf[..len] {
+                *byte = b'x';
+            }
+            self.remaining -= len;
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_body_uses_bounded_buffer() {
+        let length = 5 * 1024 * 1024;
+        let mut reader = CountingReader {
+            remaining: length + 100,
+            largest_read: 0,
+        };
+        let mut writer = CountingWriter {
+            written: Vec::new(),
+            writes: 0,
+        };
+        copy_body(&mut reader, &mut writer, length).await.unwrap();
+
+        assert_eq!(writer.written.len(), length);
+        // The bytes after the body are left for whoever reads next
+        assert_eq!(reader.remaining, 100);
+        assert!(reader.largest_read <= COPY_BUFFER_SIZE);
+    }
+
+    #[tokio::test]
+    async fn test_copy_body_sender_hangs_up() {
+        let mut reader = CountingReader {
+            remaining: 1000,
+            largest_read: 0,
+        };
+        let mut writer = CountingWriter {
+            written: Vec::new(),
+            writes: 0,
+        };
+        match copy_body(&mut reader, &mut writer, 5000).await {
+            Err(Error::IncompleteBody(1000)) => {}
+            other => panic!("Expected IncompleteBody(1000), got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_chunked_body() {
+        // The first bytes of the body arrived along with the headers
+        let mut body = b"5;ext=1\r\nhel".to_vec();
+        let mut stream: &[u8] = b"lo\r\n6\r\n world\r\n0\r\nTrailer: x\r\n\r\n";
+        read_chunked_body(&mut stream, &mut body, 100)
+            .await
+            .unwrap();
+        assert_eq!(body, b"hello world");
+
+        let mut body = Vec::new();
+        let mut stream: &[u8] = b"b\r\nhello world\r\n0\r\n\r\n";
+        match read_chunked_body(&mut stream, &mut body, 10).await {
+            Err(ChunkedError::BodyTooLarge) => {}
+            other => panic!("Expected BodyTooLarge, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_chunked_body_huge_chunk_size() {
+        let mut body = Vec::new();
+        let mut stream: &[u8] = b"5\r\nhello\r\nffffffffffffffff\r\n world\r\n0\r\n\r\n";
+        match read_chunked_body(&mut stream, &mut body, 100).await {
+            Err(ChunkedError::BodyTooLarge) => {}
+            other => panic!("Expected BodyTooLarge, got {:?}", other),
+        }
+    }
+}
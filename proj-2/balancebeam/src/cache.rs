@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Statuses whose responses may be cached (RFC 7231, section 6.1), except for 206, since we don't
+/// do anything with ranges
+const CACHEABLE_STATUSES: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// A response we've stored, along with when it was stored and when it stops being fresh
+struct Entry {
+    response: http::Response<Vec<u8>>,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+/// The cache's contents. `size` is the total size of the stored response bodies, which is what
+/// counts towards max_size.
+struct Entries {
+    by_key: HashMap<String, Entry>,
+    size: usize,
+}
+
+/// An in-memory cache of upstream responses to GET requests, keyed by method, host and URI.
+/// Responses are only cached if the upstream says how long they stay fresh for (Cache-Control:
+/// max-age).
+pub struct Cache {
+    /// Maximum total size of the response bodies we hold on to, in bytes
+    max_size: usize,
+    entries: Mutex<Entries>,
+}
+
+/// Returns the directives in the Cache-Control header(s), lowercased, as (name, value) pairs
+fn cache_control(headers: &http::HeaderMap) -> Vec<(String, Option<String>)> {
+    headers
+        .get_all("cache-control")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_string(),
+                Some(value.trim().trim_matches('"').to_string()),
+            ),
+            None => (directive, None),
+        })
+        .collect()
+}
+
+/// Identifies the requests that get the same response. The host is included because we may be
+/// serving several sites, which can have the same paths.
+fn cache_key(request: &http::Request<Vec<u8>>) -> String {
+    let host = request
+        .headers()
+        .get("host")
+        .map(|host| String::from_utf8_lossy(host.as_bytes()).to_ascii_lowercase())
+        .unwrap_or_default();
+    format!("{} {} {}", request.method(), host, request.uri())
+}
+
+impl Cache {
+    pub fn new(max_size: usize) -> Cache {
+        Cache {
+            max_size,
+            entries: Mutex::new(Entries {
+                by_key: HashMap::new(),
+                size: 0,
+            }),
+        }
+    }
+
+    /// Returns a copy of the stored response for this request, if there is one that is still
+    /// fresh. It's marked with X-Cache: HIT, and an Age header saying how long ago it was stored.
+    pub fn get(&self, request: &http::Request<Vec<u8>>) -> Option<http::Response<Vec<u8>>> {
+        if request.method() != http::Method::GET || !request.body().is_empty() {
+            return None;
+        }
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.by_key.get(&cache_key(request))?;
+        let now = Instant::now();
+        if now >= entry.expires_at {
+            return None;
+        }
+
+        let mut response = http::Response::builder()
+            .status(entry.response.status())
+            .version(entry.response.version())
+            .body(entry.response.body().clone())
+            .unwrap();
+        *response.headers_mut() = entry.response.headers().clone();
+        let age = now.duration_since(entry.stored_at).as_secs();
+        response
+            .headers_mut()
+            .insert("age", http::HeaderValue::from(age));
+        response
+            .headers_mut()
+            .insert("x-cache", http::HeaderValue::from_static("HIT"));
+        Some(response)
+    }
+
+    /// Works out how long the response to this request can be cached for, or returns None if it
+    /// shouldn't be cached at all
+    pub fn ttl(
+        request: &http::Request<Vec<u8>>,
+        response: &http::Response<Vec<u8>>,
+    ) -> Option<Duration> {
+        if request.method() != http::Method::GET
+            || !CACHEABLE_STATUSES.contains(&response.status().as_u16())
+        {
+            return None;
+        }
+        // We're a shared cache, so a response meant for one user can't be given to anyone else,
+        // and a response that varies by request header could be the wrong one for the next client
+        if request.headers().contains_key("authorization")
+            || response.headers().contains_key("vary")
+        {
+            return None;
+        }
+        let directives = cache_control(response.headers());
+        if directives
+            .iter()
+            .any(|(name, _)| name == "no-store" || name == "no-cache" || name == "private")
+        {
+            return None;
+        }
+        // s-maxage is specifically for shared caches, so it takes priority over max-age
+        let max_age = |directive: &str| {
+            directives
+                .iter()
+                .find(|(name, _)| name == directive)
+                .and_then(|(_, value)| value.as_ref()?.parse::<u64>().ok())
+        };
+        match max_age("s-maxage").or_else(|| max_age("max-age")) {
+            Some(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+            _ => None,
+        }
+    }
+
+    /// Stores a response (whose body must have been read in full) for `ttl`. If the cache is
+    /// full, expired responses are thrown out first, then the ones closest to expiring.
+    pub fn insert(
+        &self,
+        request: &http::Request<Vec<u8>>,
+        response: &http::Response<Vec<u8>>,
+        ttl: Duration,
+    ) {
+        let size = response.body().len();
+        if size > self.max_size {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let entries = &mut *entries;
+        let key = cache_key(request);
+        if let Some(old_entry) = entries.by_key.remove(&key) {
+            entries.size -= old_entry.response.body().len();
+        }
+
+        if entries.size + size > self.max_size {
+            let by_key = &mut entries.by_key;
+            by_key.retain(|_, entry| entry.expires_at > now);
+            entries.size = by_key
+                .values()
+                .map(|entry| entry.response.body().len())
+                .sum();
+        }
+        while entries.size + size > self.max_size {
+            let soonest = entries
+                .by_key
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            let evicted = entries.by_key.remove(&soonest).unwrap();
+            entries.size -= evicted.response.body().len();
+        }
+
+        let mut stored_response = http::Response::builder()
+            .status(response.status())
+            .version(response.version())
+            .body(response.body().clone())
+            .unwrap();
+        *stored_response.headers_mut() = response.headers().clone();
+        entries.by_key.insert(
+            key,
+            Entry {
+                response: stored_response,
+                stored_at: now,
+                expires_at: now + ttl,
+            },
+        );
+        entries.size += size;
+    }
+}
@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Settings read from a --config file. Every setting is optional: anything given on the command
+/// line takes precedence, and anything that isn't set in either place gets its usual default. The
+/// settings have the same names (and meanings) as the command-line options, except that upstreams
+/// are given as a list of tables:
+///
+/// ```toml
+/// bind = "0.0.0.0:1100"
+///
+/// # Health checks
+/// active_health_check_interval = 10
+/// active_health_check_path = "/healthz"
+/// health_check_expected_status = "200-299"
+/// max_failures = 3
+///
+/// # Rate limiting
+/// max_requests_per_minute = 100
+/// rate_limit_window_secs = 60
+/// max_connections_per_ip = 20
+///
+/// [[upstream]]
+/// address = "10.0.0.1:8080"
+/// weight = 2
+///
+/// [[upstream]]
+/// address = "https://backend.example.com:443" # weight defaults to 1
+/// ```
+///
+/// To serve several sites, upstreams can also be put in named pools, with each request sent to a
+/// pool based on its Host header. Each pool is load balanced on its own. Requests for any other
+/// host go to the [[upstream]] list (or default_pool, if it's set instead), or get a 404 if there
+/// isn't one:
+///
+/// ```toml
+/// default_pool = "www"
+///
+/// [[pools.api.upstream]]
+/// address = "10.0.1.1:8080"
+///
+/// [[pools.www.upstream]]
+/// address = "10.0.2.1:8080"
+///
+/// [hosts]
+/// "api.example.com" = "api"
+/// "www.example.com" = "www"
+/// ```
+///
+/// Requests can also be routed to pools by the start of their path. These routes are checked
+/// before [hosts], and when several match, the one with the longest prefix wins:
+///
+/// ```toml
+/// [[routes]]
+/// prefix = "/api"       # matches /api and /api/..., but not /apis
+/// pool = "api"
+/// strip_prefix = true   # so /api/users is passed on as /users (defaults to false)
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// IP/port to bind to
+    pub bind: Option<String>,
+    /// Upstreams to forward requests to. Any --upstream options replace this list entirely.
+    #[serde(default, rename = "upstream")]
+    pub upstreams: Vec<UpstreamConfig>,
+    /// Interval between active health checks, in seconds (0 = never)
+    pub active_health_check_interval: Option<usize>,
+    /// Path to send requests to for active health checks
+    pub active_health_check_path: Option<String>,
+    /// Statuses that count as healthy in active health checks (e.g. "200" or "200-299,304")
+    pub health_check_expected_status: Option<String>,
+    /// Number of failures in a row after which an upstream is marked dead
+    pub max_failures: Option<usize>,
+    /// Maximum number of requests to accept per IP per rate-limiting window (0 = unlimited)
+    pub max_requests_per_minute: Option<usize>,
+    /// Maximum number of connections one IP can have open at once (0 = unlimited)
+    pub max_connections_per_ip: Option<usize>,
+    /// Length of the rolling rate-limiting window, in seconds
+    pub rate_limit_window_secs: Option<u64>,
+    /// Named pools of upstreams, which requests can be routed to by host
+    #[serde(default)]
+    pub pools: BTreeMap<String, PoolConfig>,
+    /// Which pool to send requests for each host name to
+    #[serde(default)]
+    pub hosts: HashMap<String, String>,
+    /// The pool for requests that no other route matches, in place of the [[upstream]] list
+    pub default_pool: Option<String>,
+    /// Which pool to send requests to by the start of their path
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+/// One entry in [pools]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolConfig {
+    /// The upstreams in the pool, each given like a top-level [[upstream]]
+    #[serde(rename = "upstream")]
+    pub upstreams: Vec<UpstreamConfig>,
+}
+
+/// One [[upstream]] entry in a config file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpstreamConfig {
+    /// The upstream's address, written like an --upstream option ([https://]host:port), but
+    /// without the weight
+    pub address: String,
+    /// How many turns the upstream gets in round-robin, relative to the others
+    #[serde(default = "default_weight")]
+    pub weight: usize,
+}
+
+fn default_weight() -> usize {
+    1
+}
+
+impl UpstreamConfig {
+    /// Returns this upstream written the way it would be given to --upstream
+    pub fn to_upstream_arg(&self) -> String {
+        format!("{}@{}", self.address, self.weight)
+    }
+}
+
+/// One [[routes]] entry
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// The path prefix to match, which matches whole path segments only
+    pub prefix: String,
+    /// The pool to send matching requests to
+    pub pool: String,
+    /// Whether to remove the prefix from the path before passing the request on
+    #[serde(default)]
+    pub strip_prefix: bool,
+}
+
+/// Reads and parses the config file at path
+pub fn load(path: &str) -> Result<Config, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+    toml::from_str(&contents).map_err(|err| format!("invalid config in {}: {}", path, err))
+}
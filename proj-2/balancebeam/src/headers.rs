@@ -0,0 +1,68 @@
+/// Headers that only describe the connection they were sent over, rather than the request or
+/// response itself (RFC 7230, section 6.1). A proxy has its own connections on either side, so it
+/// must not pass these along.
+const HOP_BY_HOP_HEADERS: [&str; 7] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Returns true if the connection a message was sent over stays open afterwards. HTTP/1.1
+/// connections do unless the sender says otherwise (Connection: close), but HTTP/1.0 connections
+/// are closed after every message unless the sender asks to keep them open (Connection:
+/// keep-alive).
+pub fn keep_alive(version: http::Version, headers: &http::HeaderMap) -> bool {
+    let options = connection_options(headers);
+    if version <= http::Version::HTTP_10 {
+        options.iter().any(|option| option == "keep-alive")
+    } else {
+        !options.iter().any(|option| option == "close")
+    }
+}
+
+/// Returns the protocol the sender wants to switch the connection over to (the Upgrade header), if
+/// it's asking to (Connection: upgrade). Upgrade is a hop-by-hop header, but a proxy has to pass
+/// it along for the upgrade to happen at all.
+pub fn upgrade_protocol(headers: &http::HeaderMap) -> Option<http::HeaderValue> {
+    if connection_options(headers)
+        .iter()
+        .any(|option| option == "upgrade")
+    {
+        headers.get("upgrade").cloned()
+    } else {
+        None
+    }
+}
+
+/// Marks a request or response as asking to switch protocols (see upgrade_protocol)
+pub fn set_upgrade_protocol(headers: &mut http::HeaderMap, protocol: http::HeaderValue) {
+    headers.insert("connection", http::HeaderValue::from_static("upgrade"));
+    headers.insert("upgrade", protocol);
+}
+
+/// Returns the (lowercased) options listed in the Connection header(s)
+fn connection_options(headers: &http::HeaderMap) -> Vec<String> {
+    headers
+        .get_all("connection")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|option| option.trim().to_ascii_lowercase())
+        .filter(|option| !option.is_empty())
+        .collect()
+}
+
+/// Removes the hop-by-hop headers from a request or response before it gets forwarded. Besides the
+/// standard ones, the sender can mark any other header as hop-by-hop by listing it in Connection.
+pub fn remove_hop_by_hop_headers(headers: &mut http::HeaderMap) {
+    for name in connection_options(headers) {
+        headers.remove(name.as_str());
+    }
+    for name in HOP_BY_HOP_HEADERS.iter() {
+        headers.remove(*name);
+    }
+}
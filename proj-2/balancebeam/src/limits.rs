@@ -0,0 +1,12 @@
+/// Limits on the size of the requests and responses that balancebeam will handle. Anything
+/// bigger is rejected, so that a misbehaving client or server can't make us buffer (or parse)
+/// unbounded amounts of data.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Maximum size of the request/status line and headers, in bytes
+    pub max_headers_size: usize,
+    /// Maximum size of a request or response body, in bytes
+    pub max_body_size: usize,
+    /// Maximum number of headers in a request or response
+    pub max_num_headers: usize,
+}
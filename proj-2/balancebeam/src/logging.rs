@@ -0,0 +1,126 @@
+use log::kv::{Key, Value, VisitSource, VisitValue};
+use std::io::Write;
+
+/// How log messages are written out (to stderr)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Colorful lines for people to read (pretty_env_logger)
+    Text,
+    /// One JSON object per line, for log pipelines to ingest
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<LogFormat, String> {
+        match name {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}", name)),
+        }
+    }
+}
+
+/// Sets up the logger for the log macros, filtering messages by RUST_LOG either way
+pub fn init(format: LogFormat) {
+    match format {
+        LogFormat::Text => pretty_env_logger::init(),
+        LogFormat::Json => {
+            let filter = env_logger::filter::Builder::from_env("RUST_LOG").build();
+            log::set_max_level(filter.filter());
+            log::set_boxed_logger(Box::new(JsonLogger { filter }))
+                .expect("A logger was already set up");
+        }
+    }
+}
+
+/// Writes each log message as a JSON object, with its timestamp, level, target and message, along
+/// with any key-values it was logged with (e.g. the client IP and status for a finished request)
+struct JsonLogger {
+    filter: env_logger::filter::Filter,
+}
+
+impl log::Log for JsonLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let mut fields = serde_json::Map::new();
+        fields.insert(
+            "timestamp".to_string(),
+            chrono::Local::now().to_rfc3339().into(),
+        );
+        fields.insert("level".to_string(), record.level().as_str().into());
+        fields.insert("target".to_string(), record.target().into());
+        fields.insert("message".to_string(), record.args().to_string().into());
+        // A key-value can't fail to be turned into JSON, so there won't be any errors to handle
+        let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+
+        let mut line = serde_json::Value::Object(fields).to_string();
+        line.push('\n');
+        // Write the whole line at once, so that lines from different threads don't get mixed up
+        let _ = std::io::stderr().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Adds the key-values a message was logged with to its JSON object
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let mut json_value = JsonValue(serde_json::Value::Null);
+        value.visit(&mut json_value)?;
+        self.0.insert(key.to_string(), json_value.0);
+        Ok(())
+    }
+}
+
+/// Turns a key-value's value into JSON, keeping numbers and booleans as they are, and writing
+/// anything else as a string
+struct JsonValue(serde_json::Value);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: Value) -> Result<(), log::kv::Error> {
+        self.0 = value.to_string().into();
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
@@ -1,10 +1,7 @@
-use std::cmp::min;
-use std::io::{Read, Write};
-use std::net::TcpStream;
-
-const MAX_HEADERS_SIZE: usize = 8000;
-const MAX_BODY_SIZE: usize = 10000000;
-const MAX_NUM_HEADERS: usize = 32;
+use crate::body;
+use crate::limits::Limits;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[derive(Debug)]
 pub enum Error {
@@ -17,9 +14,24 @@ pub enum Error {
     InvalidContentLength,
     /// The Content-Length header does not match the size of the request body that was sent
     ContentLengthMismatch,
-    /// The request body is bigger than MAX_BODY_SIZE
+    /// The request line and headers don't fit in the maximum header size
+    HeadersTooLarge,
+    /// The client didn't finish sending the request line and headers within the header read
+    /// timeout
+    HeadersTimedOut,
+    /// The request body is bigger than the maximum body size
     RequestBodyTooLarge,
-    /// Encountered an I/O error when reading/writing a TcpStream
+    /// The request has both a Content-Length and a Transfer-Encoding, so it isn't clear where its
+    /// body ends. Clients don't send both, but an attacker might, hoping that we and the upstream
+    /// disagree about which to believe (request smuggling).
+    ConflictingBodyLength,
+    /// The request has a Transfer-Encoding that doesn't end in chunked, so (as with
+    /// ConflictingBodyLength) there's no telling where its body ends
+    UnsupportedTransferEncoding,
+    /// The request body uses chunked transfer encoding, but a chunk size line isn't a valid hex
+    /// number (or a chunk isn't followed by \r\n)
+    InvalidChunkSize,
+    /// Encountered an I/O error when reading/writing a stream
     ConnectionError(std::io::Error),
 }
 
@@ -74,8 +86,11 @@ pub fn extend_header_value(
 /// * If there is data in the buffer that is definitely not a valid HTTP request, returns Err(Error)
 ///
 /// You won't need to touch this function.
-fn parse_request(buffer: &[u8]) -> Result<Option<(http::Request<Vec<u8>>, usize)>, Error> {
-    let mut headers = [httparse::EMPTY_HEADER; MAX_NUM_HEADERS];
+fn parse_request(
+    buffer: &[u8],
+    max_num_headers: usize,
+) -> Result<Option<(http::Request<Vec<u8>>, usize)>, Error> {
+    let mut headers = vec![httparse::EMPTY_HEADER; max_num_headers];
     let mut req = httparse::Request::new(&mut headers);
     let res = req.parse(buffer).or_else(|err| Err(Error::MalformedRequest(err)))?;
 
@@ -83,7 +98,7 @@ fn parse_request(buffer: &[u8]) -> Result<Option<(http::Request<Vec<u8>>, usize)
         let mut request = http::Request::builder()
             .method(req.method.unwrap())
             .uri(req.path.unwrap())
-            .version(http::Version::HTTP_11);
+            .version(http_version(req.version.unwrap()));
         for header in req.headers {
             request = request.header(header.name, header.value);
         }
@@ -94,23 +109,38 @@ fn parse_request(buffer: &[u8]) -> Result<Option<(http::Request<Vec<u8>>, usize)
     }
 }
 
+/// Converts the minor version number httparse gives us (the x in HTTP/1.x) to an http::Version
+pub fn http_version(minor_version: u8) -> http::Version {
+    match minor_version {
+        0 => http::Version::HTTP_10,
+        _ => http::Version::HTTP_11,
+    }
+}
+
 /// Reads an HTTP request from the provided stream, waiting until a complete set of headers is sent.
-/// This function only reads the request line and headers; the read_body function can subsequently
-/// be called in order to read the request body (for a POST request).
+/// This function only reads the request line and headers; the request body (for a POST request)
+/// is either read by read_chunked_body or forwarded by the caller with body::copy_body.
 ///
 /// Returns Ok(http::Request) if a valid request is received, or Error if not.
-///
-/// You will need to modify this function in Milestone 2.
-fn read_headers(stream: &mut TcpStream) -> Result<http::Request<Vec<u8>>, Error> {
+async fn read_headers<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    limits: &Limits,
+) -> Result<http::Request<Vec<u8>>, Error> {
     // Try reading the headers from the request. We may not receive all the headers in one shot
     // (e.g. we might receive the first few bytes of a request, and then the rest follows later).
     // Try parsing repeatedly until we read a valid HTTP request
-    let mut request_buffer = [0_u8; MAX_HEADERS_SIZE];
+    let mut request_buffer = vec![0_u8; limits.max_headers_size];
     let mut bytes_read = 0;
     loop {
+        // If the buffer is full and we still don't have a complete set of headers, reading into
+        // the (empty) rest of the buffer would look just like the client hanging up
+        if bytes_read == request_buffer.len() {
+            return Err(Error::HeadersTooLarge);
+        }
         // Read bytes from the connection into the buffer, starting at position bytes_read
         let new_bytes = stream
             .read(&mut request_buffer[bytes_read..])
+            .await
             .or_else(|err| Err(Error::ConnectionError(err)))?;
         if new_bytes == 0 {
             // We didn't manage to read a complete request
@@ -119,7 +149,9 @@ fn read_headers(stream: &mut TcpStream) -> Result<http::Request<Vec<u8>>, Error>
         bytes_read += new_bytes;
 
         // See if we've read a valid request so far
-        if let Some((mut request, headers_len)) = parse_request(&request_buffer[..bytes_read])? {
+        if let Some((mut request, headers_len)) =
+            parse_request(&request_buffer[..bytes_read], limits.max_num_headers)?
+        {
             // We've read a complete set of headers. However, if this was a POST request, a request
             // body might have been included as well, and we might have read part of the body out of
             // the stream into header_buffer. We need to add those bytes to the Request body so that
@@ -132,87 +164,195 @@ fn read_headers(stream: &mut TcpStream) -> Result<http::Request<Vec<u8>>, Error>
     }
 }
 
-/// This function reads the body for a request from the stream. The client only sends a body if the
-/// Content-Length header is present; this function reads that number of bytes from the stream. It
-/// returns Ok(()) if successful, or Err(Error) if Content-Length bytes couldn't be read.
-///
-/// You will need to modify this function in Milestone 2.
-fn read_body(
-    stream: &mut TcpStream,
+/// Returns true if the client is waiting to hear from us before it sends the body (Expect:
+/// 100-continue), removing the header, since we're the ones who answer it. (Passing it along would
+/// get us an interim 100 Continue from the upstream before its real response.)
+fn take_continue_expectation(request: &mut http::Request<Vec<u8>>) -> bool {
+    let expects_continue = request
+        .headers()
+        .get("expect")
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"));
+    if expects_continue {
+        request.headers_mut().remove("expect");
+    }
+    // HTTP/1.0 clients don't know about interim responses, and if some of the body has already
+    // arrived, the client has stopped waiting anyway
+    expects_continue && request.version() >= http::Version::HTTP_11 && request.body().is_empty()
+}
+
+/// Tells a client that sent Expect: 100-continue to go ahead and send the body
+async fn send_continue<S: AsyncWrite + Unpin>(stream: &mut S) -> Result<(), Error> {
+    stream
+        .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+        .await
+        .map_err(Error::ConnectionError)
+}
+
+/// This function reads a body sent with chunked transfer encoding (see body::read_chunked_body).
+/// The decoded body is stored in the request, and the Transfer-Encoding header is replaced with a
+/// Content-Length, since the body is forwarded as-is.
+async fn read_chunked_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
     request: &mut http::Request<Vec<u8>>,
-    content_length: usize,
+    max_body_size: usize,
 ) -> Result<(), Error> {
-    // Keep reading data until we read the full body length, or until we hit an error.
-    while request.body().len() < content_length {
-        // Read up to 512 bytes at a time. (If the client only sent a small body, then only allocate
-        // space to read that body.)
-        let mut buffer = vec![0_u8; min(512, content_length)];
-        let bytes_read = stream.read(&mut buffer).or_else(|err| Err(Error::ConnectionError(err)))?;
-
-        // Make sure the client is still sending us bytes
-        if bytes_read == 0 {
-            log::debug!(
-                "Client hung up after sending a body of length {}, even though it said the content \
-                length is {}",
-                request.body().len(),
-                content_length
-            );
-            return Err(Error::ContentLengthMismatch);
-        }
+    body::read_chunked_body(stream, request.body_mut(), max_body_size)
+        .await
+        .map_err(|error| match error {
+            body::ChunkedError::IncompleteBody(bytes) => Error::IncompleteRequest(bytes),
+            body::ChunkedError::InvalidChunkSize => Error::InvalidChunkSize,
+            body::ChunkedError::BodyTooLarge => Error::RequestBodyTooLarge,
+            body::ChunkedError::ReadFailed(error) => Error::ConnectionError(error),
+        })?;
 
-        // Make sure the client didn't send us *too many* bytes
-        if request.body().len() + bytes_read > content_length {
-            log::debug!(
-                "Client sent more bytes than we expected based on the given content length!"
-            );
-            return Err(Error::ContentLengthMismatch);
-        }
-
-        // Store the received bytes in the request body
-        request.body_mut().extend_from_slice(&buffer[..bytes_read]);
-    }
+    let content_length = request.body().len().to_string();
+    let headers = request.headers_mut();
+    headers.remove("transfer-encoding");
+    headers.insert(
+        "content-length",
+        http::HeaderValue::from_str(&content_length).unwrap(),
+    );
     Ok(())
 }
 
-/// This function reads and returns an HTTP request from a stream, returning an Error if the client
-/// closes the connection prematurely or sends an invalid request.
+/// This function reads an HTTP request from a stream, returning an Error if the client closes the
+/// connection prematurely or sends an invalid request.
 ///
-/// You will need to modify this function in Milestone 2.
-pub fn read_from_stream(stream: &mut TcpStream) -> Result<http::Request<Vec<u8>>, Error> {
-    // Read headers
-    let mut request = read_headers(stream)?;
-    // Read body if the client supplied the Content-Length header (which it does for POST requests)
-    if let Some(content_length) = get_content_length(&request)? {
-        if content_length > MAX_BODY_SIZE {
+/// A body with a Content-Length is not read in full. The returned request only contains whatever
+/// part of the body arrived along with the headers, and the number of body bytes still waiting in
+/// the stream is returned alongside it, so that the caller can forward them with body::copy_body.
+/// (Chunked bodies are still read and decoded in full.)
+///
+/// If the client sent Expect: 100-continue, it gets a 100 Continue once we know the body isn't too
+/// big (or the error straight away if it is).
+pub async fn read_from_stream<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    limits: &Limits,
+    header_read_timeout: Duration,
+) -> Result<(http::Request<Vec<u8>>, usize), Error> {
+    // Read headers, giving up if the client takes too long. (Otherwise a client could hold on to
+    // the connection forever by sending one byte every few seconds.)
+    let mut request = tokio::time::timeout(header_read_timeout, read_headers(stream, limits))
+        .await
+        .or(Err(Error::HeadersTimedOut))??;
+    if request.headers().contains_key("content-length")
+        && request.headers().contains_key("transfer-encoding")
+    {
+        return Err(Error::ConflictingBodyLength);
+    }
+    // Only a chunked body has an end we can find. Anything else (e.g. gzip, or chunked, identity)
+    // would leave the body to be read as the next request once the header is stripped.
+    if request.headers().contains_key("transfer-encoding") && !body::is_chunked(request.headers()) {
+        return Err(Error::UnsupportedTransferEncoding);
+    }
+    let expects_continue = take_continue_expectation(&mut request);
+    // Check the body if the client supplied the Content-Length header (which it does for POST
+    // requests) or read it if it was sent in chunks
+    if body::is_chunked(request.headers()) {
+        if expects_continue {
+            send_continue(stream).await?;
+        }
+        read_chunked_body(stream, &mut request, limits.max_body_size).await?;
+    } else if let Some(content_length) = get_content_length(&request)? {
+        if content_length > limits.max_body_size {
             return Err(Error::RequestBodyTooLarge);
-        } else {
-            read_body(stream, &mut request, content_length)?;
         }
+        if expects_continue && content_length > 0 {
+            send_continue(stream).await?;
+        }
+        if request.body().len() > content_length {
+            log::debug!(
+                "Client sent more bytes than we expected based on the given content length!"
+            );
+            return Err(Error::ContentLengthMismatch);
+        }
+        let remaining = content_length - request.body().len();
+        return Ok((request, remaining));
     }
-    Ok(request)
+    Ok((request, 0))
 }
 
-/// This function serializes a request to bytes and writes those bytes to the provided stream.
-///
-/// You will need to modify this function in Milestone 2.
-pub fn write_to_stream(
+/// This function serializes a request to bytes and writes those bytes to the provided stream. The
+/// whole request goes out in one write, rather than a write per header, so that it isn't split up
+/// into lots of tiny packets.
+pub async fn write_to_stream<S: AsyncWrite + Unpin>(
     request: &http::Request<Vec<u8>>,
-    stream: &mut TcpStream,
+    stream: &mut S,
 ) -> Result<(), std::io::Error> {
-    stream.write(&format_request_line(request).into_bytes())?;
-    stream.write(&['\r' as u8, '\n' as u8])?; // \r\n
+    let mut buffer = format_request_line(request).into_bytes();
+    buffer.extend_from_slice(b"\r\n");
     for (header_name, header_value) in request.headers() {
-        stream.write(&format!("{}: ", header_name).as_bytes())?;
-        stream.write(header_value.as_bytes())?;
-        stream.write(&['\r' as u8, '\n' as u8])?; // \r\n
-    }
-    stream.write(&['\r' as u8, '\n' as u8])?;
-    if request.body().len() > 0 {
-        stream.write(request.body())?;
+        buffer.extend_from_slice(header_name.as_str().as_bytes());
+        buffer.extend_from_slice(b": ");
+        buffer.extend_from_slice(header_value.as_bytes());
+        buffer.extend_from_slice(b"\r\n");
     }
-    Ok(())
+    buffer.extend_from_slice(b"\r\n");
+    buffer.extend_from_slice(request.body());
+    stream.write_all(&buffer).await
 }
 
 pub fn format_request_line(request: &http::Request<Vec<u8>>) -> String {
     format!("{} {} {:?}", request.method(), request.uri(), request.version())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::body::test_writers::{CountingWriter, TrickleWriter};
+
+    #[test]
+    fn test_parse_request_keeps_version() {
+        let (request, _) = parse_request(b"GET /old HTTP/1.0\r\n\r\n", 8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.version(), http::Version::HTTP_10);
+        let (request, _) = parse_request(b"GET /new HTTP/1.1\r\nHost: localhost\r\n\r\n", 8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.version(), http::Version::HTTP_11);
+    }
+
+    #[tokio::test]
+    async fn test_write_to_stream_partial_writes() {
+        let body = vec![b'x'; 100000];
+        let request = http::Request::builder()
+            .method(http::Method::POST)
+            .uri("/upload")
+            .header("content-length", body.len().to_string())
+            .body(body.clone())
+            .unwrap();
+        let mut writer = TrickleWriter {
+            written: Vec::new(),
+        };
+        write_to_stream(&request, &mut writer).await.unwrap();
+
+        let mut expected = b"POST /upload HTTP/1.1\r\ncontent-length: 100000\r\n\r\n".to_vec();
+        expected.extend_from_slice(&body);
+        assert_eq!(writer.written, expected);
+    }
+
+    #[tokio::test]
+    async fn test_write_to_stream_single_write() {
+        let request = http::Request::builder()
+            .method(http::Method::GET)
+            .uri("/index.html?page=2")
+            .header("host", "example.com")
+            .header("accept", "text/html")
+            .header("accept", "text/plain")
+            .body(Vec::new())
+            .unwrap();
+        let mut writer = CountingWriter {
+            written: Vec::new(),
+            writes: 0,
+        };
+        write_to_stream(&request, &mut writer).await.unwrap();
+
+        assert_eq!(writer.writes, 1);
+        assert_eq!(
+            writer.written,
+            &b"GET /index.html?page=2 HTTP/1.1\r\nhost: example.com\r\naccept: text/html\r\n\
+            accept: text/plain\r\n\r\n"[..]
+        );
+    }
+}
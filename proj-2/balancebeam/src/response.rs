@@ -1,9 +1,6 @@
-use std::io::{Read, Write};
-use std::net::TcpStream;
-
-const MAX_HEADERS_SIZE: usize = 8000;
-const MAX_BODY_SIZE: usize = 10000000;
-const MAX_NUM_HEADERS: usize = 32;
+use crate::body;
+use crate::limits::Limits;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[derive(Debug)]
 pub enum Error {
@@ -15,9 +12,12 @@ pub enum Error {
     InvalidContentLength,
     /// The Content-Length header does not match the size of the request body that was sent
     ContentLengthMismatch,
-    /// The request body is bigger than MAX_BODY_SIZE
+    /// The request body is bigger than the maximum body size
     ResponseBodyTooLarge,
-    /// Encountered an I/O error when reading/writing a TcpStream
+    /// The response body uses chunked transfer encoding, but a chunk size line isn't a valid hex
+    /// number (or a chunk isn't followed by \r\n)
+    InvalidChunkSize,
+    /// Encountered an I/O error when reading/writing a stream
     ConnectionError(std::io::Error),
 }
 
@@ -52,8 +52,11 @@ fn get_content_length(response: &http::Response<Vec<u8>>) -> Result<Option<usize
 ///   Err(Error)
 ///
 /// You won't need to touch this function.
-fn parse_response(buffer: &[u8]) -> Result<Option<(http::Response<Vec<u8>>, usize)>, Error> {
-    let mut headers = [httparse::EMPTY_HEADER; MAX_NUM_HEADERS];
+fn parse_response(
+    buffer: &[u8],
+    max_num_headers: usize,
+) -> Result<Option<(http::Response<Vec<u8>>, usize)>, Error> {
+    let mut headers = vec![httparse::EMPTY_HEADER; max_num_headers];
     let mut resp = httparse::Response::new(&mut headers);
     let res = resp
         .parse(buffer)
@@ -62,7 +65,7 @@ fn parse_response(buffer: &[u8]) -> Result<Option<(http::Response<Vec<u8>>, usiz
     if let httparse::Status::Complete(len) = res {
         let mut response = http::Response::builder()
             .status(resp.code.unwrap())
-            .version(http::Version::HTTP_11);
+            .version(crate::request::http_version(resp.version.unwrap()));
         for header in resp.headers {
             response = response.header(header.name, header.value);
         }
@@ -78,18 +81,20 @@ fn parse_response(buffer: &[u8]) -> Result<Option<(http::Response<Vec<u8>>, usiz
 /// subsequently be called in order to read the response body.
 ///
 /// Returns Ok(http::Response) if a valid response is received, or Error if not.
-///
-/// You will need to modify this function in Milestone 2.
-fn read_headers(stream: &mut TcpStream) -> Result<http::Response<Vec<u8>>, Error> {
+async fn read_headers<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    limits: &Limits,
+) -> Result<http::Response<Vec<u8>>, Error> {
     // Try reading the headers from the response. We may not receive all the headers in one shot
     // (e.g. we might receive the first few bytes of a response, and then the rest follows later).
     // Try parsing repeatedly until we read a valid HTTP response
-    let mut response_buffer = [0_u8; MAX_HEADERS_SIZE];
+    let mut response_buffer = vec![0_u8; limits.max_headers_size];
     let mut bytes_read = 0;
     loop {
         // Read bytes from the connection into the buffer, starting at position bytes_read
         let new_bytes = stream
             .read(&mut response_buffer[bytes_read..])
+            .await
             .or_else(|err| Err(Error::ConnectionError(err)))?;
         if new_bytes == 0 {
             // We didn't manage to read a complete response
@@ -98,7 +103,9 @@ fn read_headers(stream: &mut TcpStream) -> Result<http::Response<Vec<u8>>, Error
         bytes_read += new_bytes;
 
         // See if we've read a valid response so far
-        if let Some((mut response, headers_len)) = parse_response(&response_buffer[..bytes_read])? {
+        if let Some((mut response, headers_len)) =
+            parse_response(&response_buffer[..bytes_read], limits.max_num_headers)?
+        {
             // We've read a complete set of headers. We may have also read the first part of the
             // response body; take whatever is left over in the response buffer and save that as
             // the start of the response body.
@@ -110,59 +117,74 @@ fn read_headers(stream: &mut TcpStream) -> Result<http::Response<Vec<u8>>, Error
     }
 }
 
-/// This function reads the body for a response from the stream. If the Content-Length header is
-/// present, it reads that many bytes; otherwise, it reads bytes until the connection is closed.
-///
-/// You will need to modify this function in Milestone 2.
-fn read_body(stream: &mut TcpStream, response: &mut http::Response<Vec<u8>>) -> Result<(), Error> {
-    // The response may or may not supply a Content-Length header. If it provides the header, then
-    // we want to read that number of bytes; if it does not, we want to keep reading bytes until
-    // the connection is closed.
-    let content_length = get_content_length(response)?;
-
-    while content_length.is_none() || response.body().len() < content_length.unwrap() {
+/// This function reads the body for a response that doesn't have a Content-Length header (and
+/// isn't chunked), which means the body continues until the server closes the connection.
+async fn read_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    response: &mut http::Response<Vec<u8>>,
+    max_body_size: usize,
+) -> Result<(), Error> {
+    loop {
         let mut buffer = [0_u8; 512];
         let bytes_read = stream
             .read(&mut buffer)
+            .await
             .or_else(|err| Err(Error::ConnectionError(err)))?;
         if bytes_read == 0 {
-            // The server has hung up!
-            if content_length.is_none() {
-                // We've reached the end of the response
-                break;
-            } else {
-                // Content-Length was set, but the server hung up before we managed to read that
-                // number of bytes
-                return Err(Error::ContentLengthMismatch);
-            }
-        }
-
-        // Make sure the server doesn't send more bytes than it promised to send
-        if content_length.is_some() && response.body().len() + bytes_read > content_length.unwrap()
-        {
-            return Err(Error::ContentLengthMismatch);
+            // The server has hung up, so we've reached the end of the response
+            return Ok(());
         }
 
         // Make sure server doesn't send more bytes than we allow
-        if response.body().len() + bytes_read > MAX_BODY_SIZE {
+        if response.body().len() + bytes_read > max_body_size {
             return Err(Error::ResponseBodyTooLarge);
         }
 
         // Append received bytes to the response body
         response.body_mut().extend_from_slice(&buffer[..bytes_read]);
     }
+}
+
+/// This function reads a body sent with chunked transfer encoding (see body::read_chunked_body).
+/// The decoded body is stored in the response, and the Transfer-Encoding header is replaced with a
+/// Content-Length, since the body is forwarded to the client as-is.
+async fn read_chunked_body<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    response: &mut http::Response<Vec<u8>>,
+    max_body_size: usize,
+) -> Result<(), Error> {
+    body::read_chunked_body(stream, response.body_mut(), max_body_size)
+        .await
+        .map_err(|error| match error {
+            body::ChunkedError::IncompleteBody(_) => Error::IncompleteResponse,
+            body::ChunkedError::InvalidChunkSize => Error::InvalidChunkSize,
+            body::ChunkedError::BodyTooLarge => Error::ResponseBodyTooLarge,
+            body::ChunkedError::ReadFailed(error) => Error::ConnectionError(error),
+        })?;
+
+    let content_length = response.body().len().to_string();
+    let headers = response.headers_mut();
+    headers.remove("transfer-encoding");
+    headers.insert(
+        "content-length",
+        http::HeaderValue::from_str(&content_length).unwrap(),
+    );
     Ok(())
 }
 
-/// This function reads and returns an HTTP response from a stream, returning an Error if the server
-/// closes the connection prematurely or sends an invalid response.
+/// This function reads an HTTP response from a stream, returning an Error if the server closes the
+/// connection prematurely or sends an invalid response.
 ///
-/// You will need to modify this function in Milestone 2.
-pub fn read_from_stream(
-    stream: &mut TcpStream,
+/// As with requests, a body with a Content-Length is not read in full. The returned response only
+/// contains whatever part of the body arrived along with the headers, and the number of body bytes
+/// still waiting in the stream is returned alongside it, so that the caller can forward them with
+/// body::copy_body.
+pub async fn read_from_stream<S: AsyncRead + Unpin>(
+    stream: &mut S,
     request_method: &http::Method,
-) -> Result<http::Response<Vec<u8>>, Error> {
-    let mut response = read_headers(stream)?;
+    limits: &Limits,
+) -> Result<(http::Response<Vec<u8>>, usize), Error> {
+    let mut response = read_headers(stream, limits).await?;
     // A response may have a body as long as it is not responding to a HEAD request and as long as
     // the response status code is not 1xx, 204 (no content), or 304 (not modified).
     if !(request_method == http::Method::HEAD
@@ -170,30 +192,53 @@ pub fn read_from_stream(
         || response.status() == http::StatusCode::NO_CONTENT
         || response.status() == http::StatusCode::NOT_MODIFIED)
     {
-        read_body(stream, &mut response)?;
+        if body::is_chunked(response.headers()) {
+            read_chunked_body(stream, &mut response, limits.max_body_size).await?;
+        } else if let Some(content_length) = get_content_length(&response)? {
+            if content_length > limits.max_body_size {
+                return Err(Error::ResponseBodyTooLarge);
+            }
+            // Make sure the server doesn't send more bytes than it promised to send
+            if response.body().len() > content_length {
+                return Err(Error::ContentLengthMismatch);
+            }
+            let remaining = content_length - response.body().len();
+            return Ok((response, remaining));
+        } else {
+            read_body(stream, &mut response, limits.max_body_size).await?;
+            // The body ended when the server hung up. The client can't tell that from our
+            // connection (which may stay open), so give it the length instead, and mark the
+            // server's connection as closed so that it isn't used again.
+            let content_length = response.body().len().to_string();
+            let headers = response.headers_mut();
+            headers.insert(
+                "content-length",
+                http::HeaderValue::from_str(&content_length).unwrap(),
+            );
+            headers.insert("connection", http::HeaderValue::from_static("close"));
+        }
     }
-    Ok(response)
+    Ok((response, 0))
 }
 
-/// This function serializes a response to bytes and writes those bytes to the provided stream.
-///
-/// You will need to modify this function in Milestone 2.
-pub fn write_to_stream(
+/// This function serializes a response to bytes and writes those bytes to the provided stream. The
+/// whole response goes out in one write, rather than a write per header, so that it isn't split up
+/// into lots of tiny packets.
+pub async fn write_to_stream<S: AsyncWrite + Unpin>(
     response: &http::Response<Vec<u8>>,
-    stream: &mut TcpStream,
+    stream: &mut S,
 ) -> Result<(), std::io::Error> {
-    stream.write(&format_response_line(response).into_bytes())?;
-    stream.write(&['\r' as u8, '\n' as u8])?; // \r\n
+    let mut buffer = format_response_line(response).into_bytes();
+    buffer.extend_from_slice(b"\r\n");
     for (header_name, header_value) in response.headers() {
-        stream.write(&format!("{}: ", header_name).as_bytes())?;
-        stream.write(header_value.as_bytes())?;
-        stream.write(&['\r' as u8, '\n' as u8])?; // \r\n
-    }
-    stream.write(&['\r' as u8, '\n' as u8])?;
-    if response.body().len() > 0 {
-        stream.write(response.body())?;
+        buffer.extend_from_slice(header_name.as_str().as_bytes());
+        buffer.extend_from_slice(b": ");
+        buffer.extend_from_slice(header_value.as_bytes());
+        buffer.extend_from_slice(b"\r\n");
     }
-    Ok(())
+    buffer.extend_from_slice(b"\r\n");
+    buffer.extend_from_slice(response.body());
+    stream.write_all(&buffer).await
 }
 
 pub fn format_response_line(response: &http::Response<Vec<u8>>) -> String {
@@ -208,17 +253,71 @@ pub fn format_response_line(response: &http::Response<Vec<u8>>) -> String {
 /// This is a helper function that creates an http::Response containing an HTTP error that can be
 /// sent to a client.
 pub fn make_http_error(status: http::StatusCode) -> http::Response<Vec<u8>> {
+    make_http_error_with_headers(status, &[])
+}
+
+/// Like make_http_error, but with some extra headers to tell the client more about the error (e.g.
+/// Retry-After)
+pub fn make_http_error_with_headers(
+    status: http::StatusCode,
+    extra_headers: &[(&str, String)],
+) -> http::Response<Vec<u8>> {
     let body = format!(
         "HTTP {} {}",
         status.as_u16(),
         status.canonical_reason().unwrap_or("")
     )
     .into_bytes();
-    http::Response::builder()
+    let mut builder = http::Response::builder()
         .status(status)
         .header("Content-Type", "text/plain")
         .header("Content-Length", body.len().to_string())
-        .version(http::Version::HTTP_11)
-        .body(body)
-        .unwrap()
+        .version(http::Version::HTTP_11);
+    for (name, value) in extra_headers {
+        builder = builder.header(*name, value.as_str());
+    }
+    builder.body(body).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::body::test_writers::{CountingWriter, TrickleWriter};
+
+    #[tokio::test]
+    async fn test_write_to_stream_partial_writes() {
+        let response = make_http_error(http::StatusCode::BAD_GATEWAY);
+        let mut writer = TrickleWriter {
+            written: Vec::new(),
+        };
+        write_to_stream(&response, &mut writer).await.unwrap();
+
+        let head_len = writer.written.len() - response.body().len();
+        assert!(writer.written.starts_with(b"HTTP/1.1 502 Bad Gateway\r\n"));
+        assert!(writer.written[..head_len].ends_with(b"\r\n\r\n"));
+        assert_eq!(&writer.written[head_len..], &response.body()[..]);
+    }
+
+    #[tokio::test]
+    async fn test_write_to_stream_single_write() {
+        let response = http::Response::builder()
+            .status(http::StatusCode::OK)
+            .header("content-type", "text/plain")
+            .header("set-cookie", "a=1")
+            .header("set-cookie", "b=2")
+            .body(b"Hello world!".to_vec())
+            .unwrap();
+        let mut writer = CountingWriter {
+            written: Vec::new(),
+            writes: 0,
+        };
+        write_to_stream(&response, &mut writer).await.unwrap();
+
+        assert_eq!(writer.writes, 1);
+        assert_eq!(
+            writer.written,
+            &b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nset-cookie: a=1\r\n\
+            set-cookie: b=2\r\n\r\nHello world!"[..]
+        );
+    }
 }
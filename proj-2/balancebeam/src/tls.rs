@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{
+    Certificate, ClientConfig, NoClientAuth, PrivateKey, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, ServerConfig, TLSError,
+};
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::{client, TlsAcceptor, TlsConnector};
+
+/// Reads every certificate from a PEM file (the server's own certificate, followed by any
+/// intermediates)
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|err| format!("can't open {}: {}", path, err))?;
+    match pemfile::certs(&mut BufReader::new(file)) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(format!("no certificates found in {}", path)),
+    }
+}
+
+/// Reads a private key from a PEM file, which may be in either PKCS#8 ("BEGIN PRIVATE KEY") or
+/// PKCS#1 ("BEGIN RSA PRIVATE KEY") format
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    let open = || File::open(path).map_err(|err| format!("can't open {}: {}", path, err));
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(open()?)).unwrap_or_default();
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(open()?)).unwrap_or_default();
+    }
+    keys.pop()
+        .ok_or_else(|| format!("no private key found in {}", path))
+}
+
+/// Builds the acceptor used to perform the TLS handshake with clients, presenting the certificate
+/// in cert_path (with its private key in key_path)
+pub fn make_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(load_certs(cert_path)?, load_key(key_path)?)
+        .map_err(|err| format!("invalid certificate or key: {}", err))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts whatever certificate an upstream presents (for --insecure-upstream)
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Builds the connector used to perform the TLS handshake with https:// upstreams. Their
+/// certificates are checked against the usual public CAs, unless insecure is set, in which case
+/// they aren't checked at all.
+pub fn make_connector(insecure: bool) -> TlsConnector {
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    if insecure {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+    TlsConnector::from(Arc::new(config))
+}
+
+/// A connection to an upstream, which is plain TCP unless the upstream was given as https://
+pub enum UpstreamStream {
+    Plain(TcpStream),
+    Tls(Box<client::TlsStream<TcpStream>>),
+}
+
+impl UpstreamStream {
+    /// Returns the TCP connection underneath (which, for a TLS connection, carries encrypted data)
+    pub fn tcp_stream(&mut self) -> &mut TcpStream {
+        match self {
+            UpstreamStream::Plain(stream) => stream,
+            UpstreamStream::Tls(stream) => stream.get_mut().0,
+        }
+    }
+}
+
+impl AsyncRead for UpstreamStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for UpstreamStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            UpstreamStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            UpstreamStream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
@@ -1,7 +1,13 @@
 mod common;
 
-use common::{init_logging, BalanceBeam, EchoServer, Server};
+use common::{
+    init_logging, read_raw_response, BalanceBeam, CacheableServer, ChunkedEchoServer,
+    CloseDelimitedServer, EchoServer, Server, SlowServer, UpgradeEchoServer,
+};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::delay_for;
 
 async fn setup() -> (BalanceBeam, EchoServer) {
     init_logging();
@@ -99,3 +105,1192 @@ async fn test_multiple_requests_per_connection() {
 
     log::info!("All done :)");
 }
+
+/// Test that requests arriving in pieces are reassembled: send the request line and part of the
+/// headers, wait a bit, then send the rest of the headers and the body.
+#[tokio::test]
+async fn test_request_split_across_writes() {
+    let (balancebeam, upstream) = setup().await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending the first half of a request");
+    conn.write_all(b"POST /split_url HTTP/1.1\r\nHost: localhost\r\nx-sent-")
+        .await
+        .expect("Error sending request to balancebeam");
+    delay_for(Duration::from_millis(500)).await;
+    log::info!("Sending the rest of the request");
+    conn.write_all(b"by: balancebeam-tests\r\nContent-Length: 12\r\n\r\nHello world!")
+        .await
+        .expect("Error sending request to balancebeam");
+
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("POST /split_url HTTP/1.1"));
+    assert!(response.body.contains("x-sent-by: balancebeam-tests"));
+    assert!(response.body.contains("\n\nHello world!"));
+
+    log::info!("Checking that the origin server received 1 request");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 1,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Send a request body using chunked transfer encoding, and make sure the upstream receives the
+/// reassembled body (with a Content-Length instead of the chunked framing)
+#[tokio::test]
+async fn test_chunked_request_body() {
+    let (balancebeam, upstream) = setup().await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending a chunked request, split in the middle of a chunk");
+    conn.write_all(
+        b"POST /chunked_url HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+        5\r\nHello\r\n7;ext=1\r\n wo",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+    delay_for(Duration::from_millis(500)).await;
+    conn.write_all(b"rld!\r\n0\r\nx-trailer: ignored\r\n\r\n")
+        .await
+        .expect("Error sending request to balancebeam");
+
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("POST /chunked_url HTTP/1.1"));
+    assert!(response.body.contains("content-length: 12"));
+    assert!(!response.body.contains("transfer-encoding"));
+    assert!(response.body.ends_with("\n\nHello world!"));
+
+    log::info!("Sending a chunked request with a chunk size that isn't hex");
+    conn.write_all(
+        b"POST /bad_chunk HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+        zz\r\nHello\r\n0\r\n\r\n",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 400);
+
+    log::info!("Checking that the origin server only received the valid request");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 1,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Make sure chunked responses from the upstream are decoded before being sent on to the client,
+/// with a Content-Length in place of the chunked framing
+#[tokio::test]
+async fn test_chunked_response_body() {
+    init_logging();
+    let upstream = ChunkedEchoServer::new().await;
+    let balancebeam = BalanceBeam::new(&[&upstream.address], None, None).await;
+
+    let mut conn = balancebeam.connect().await;
+    for i in 0..2 {
+        log::info!(
+            "Sending request #{} to an upstream that responds in chunks",
+            i
+        );
+        let body = format!("Hello world #{}!", i);
+        conn.write_all(
+            format!(
+                "POST /chunked_response HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .as_bytes(),
+        )
+        .await
+        .expect("Error sending request to balancebeam");
+        let response = read_raw_response(&mut conn)
+            .await
+            .expect("balancebeam closed the connection without responding");
+        assert_eq!(response.status, 200);
+        assert!(response.headers.get("transfer-encoding").is_none());
+        assert!(response.body.contains("POST /chunked_response HTTP/1.1"));
+        assert!(response.body.ends_with(&format!("\n\n{}", body)));
+    }
+
+    log::info!("Checking that the origin server received 2 requests");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 2,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Start balancebeam with a small maximum body size, and make sure bodies over the limit are
+/// rejected with 413 (while smaller ones still go through). The limit applies to responses too, so
+/// it needs to leave room for the echo server repeating the request headers back.
+#[tokio::test]
+async fn test_custom_max_body_size() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--max-body-size", "1000"]).await;
+    let client = reqwest::Client::new();
+
+    log::info!("Sending a POST request with a body within the limit");
+    let response = client
+        .post(&format!("http://{}/small_body", balancebeam.address))
+        .body("Hello world!")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 200);
+
+    log::info!("Sending a POST request with a body over the limit");
+    let response = client
+        .post(&format!("http://{}/big_body", balancebeam.address))
+        .body("Hello world! This body is too big. ".repeat(100))
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 413);
+
+    log::info!("Checking that only the small request reached the origin server");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 1,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Send a body big enough that it has to be forwarded in many pieces (in both directions, since
+/// the echo server sends it back), and make sure it arrives intact
+#[tokio::test]
+async fn test_large_body() {
+    let (balancebeam, upstream) = setup().await;
+    let body: String = (0..4 * 1024 * 1024)
+        .map(|i| (b'a' + (i % 26) as u8) as char)
+        .collect();
+
+    log::info!("Sending a POST request with a 4MB body");
+    let response_text = balancebeam
+        .post("/large_body", &body)
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("POST /large_body HTTP/1.1"));
+    assert!(response_text.ends_with(&format!("\n\n{}", body)));
+
+    log::info!("Checking that the origin server received 1 request");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 1,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Send headers that don't fit in balancebeam's header buffer, and make sure it responds with 431
+/// instead of forwarding anything
+#[tokio::test]
+async fn test_headers_too_large() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--max-header-size", "1000"]).await;
+
+    // Send exactly enough to fill the buffer, without ever finishing the headers. (If we sent
+    // more, the unread bytes could make the connection reset before we read the response.)
+    let mut request = b"GET /big_headers HTTP/1.1\r\nx-padding: ".to_vec();
+    request.resize(1000, b'a');
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending oversized request headers");
+    conn.write_all(&request)
+        .await
+        .expect("Error sending request to balancebeam");
+
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 431);
+
+    log::info!("Checking that the origin server received no requests");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 0,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Trickle a request's headers in a byte at a time, the way a Slowloris attack would, and make
+/// sure balancebeam gives up once the header read timeout has passed (rather than waiting for as
+/// long as bytes keep arriving)
+#[tokio::test]
+async fn test_header_read_timeout() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--header-read-timeout", "2"]).await;
+
+    let start = Instant::now();
+    let mut conn = balancebeam.connect().await;
+    log::info!("Trickling in request headers");
+    // Each byte arrives well within the timeout, but we stop before the timeout is up. (If we kept
+    // sending after balancebeam hung up, the connection could be reset before we read the
+    // response.)
+    for byte in b"GET /slow HTTP/1.1\r\n" {
+        if start.elapsed() > Duration::from_millis(1500) {
+            break;
+        }
+        conn.write_all(&[*byte])
+            .await
+            .expect("Error sending request to balancebeam");
+        delay_for(Duration::from_millis(300)).await;
+    }
+
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 408);
+    assert!(
+        start.elapsed() < Duration::from_millis(3200),
+        "balancebeam should time out 2 seconds after the request started, not after the last byte"
+    );
+
+    log::info!("Checking that the origin server received no requests");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 0,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Send hop-by-hop headers (including a custom one named in Connection), and make sure none of
+/// them are passed along to the upstream
+#[tokio::test]
+async fn test_hop_by_hop_headers_removed() {
+    let (balancebeam, upstream) = setup().await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending a request with hop-by-hop headers");
+    conn.write_all(
+        b"GET /hop_by_hop HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Connection: Keep-Alive, X-Custom\r\n\
+        Keep-Alive: timeout=5\r\n\
+        X-Custom: secret\r\n\
+        x-sent-by: balancebeam-tests\r\n\r\n",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("GET /hop_by_hop HTTP/1.1"));
+    assert!(response.body.contains("x-sent-by: balancebeam-tests"));
+    assert!(!response.body.contains("connection:"));
+    assert!(!response.body.contains("keep-alive:"));
+    assert!(!response.body.contains("x-custom:"));
+
+    log::info!("Checking that the origin server received 1 request");
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 1,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
+/// Connections to the upstream should be kept open and reused once a client is done with them,
+/// instead of opening a new one for every client
+#[tokio::test]
+async fn test_upstream_connection_reuse() {
+    let (balancebeam, upstream) = setup().await;
+    let n_requests = 20;
+
+    // Each of these requests comes in on its own client connection
+    for i in 0..n_requests {
+        let path = format!("/request-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+
+    let connections_accepted = upstream.connections_accepted();
+    log::info!(
+        "Upstream accepted {} connections for {} requests",
+        connections_accepted,
+        n_requests
+    );
+    assert!(
+        connections_accepted < n_requests / 2,
+        "balancebeam opened {} upstream connections for {} requests. Are connections being reused?",
+        connections_accepted,
+        n_requests
+    );
+    assert_eq!(Box::new(upstream).stop().await, n_requests);
+
+    log::info!("All done :)");
+}
+
+/// If the upstream takes longer than --upstream-timeout-secs to respond, the client should get a
+/// 504 instead of waiting forever
+#[tokio::test]
+async fn test_upstream_timeout() {
+    init_logging();
+    let upstream = SlowServer::new(Duration::from_secs(3)).await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--upstream-timeout-secs", "1"]).await;
+
+    let start = Instant::now();
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&format!("http://{}/slow", balancebeam.address))
+        .header("x-sent-by", "balancebeam-tests")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 504);
+    assert!(
+        start.elapsed() < Duration::from_millis(2500),
+        "balancebeam took {:?} to give up on the upstream",
+        start.elapsed()
+    );
+
+    // The upstream did get the request; it just didn't answer in time
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that an upstream that is slow, but answers within --upstream-timeout-secs, still gets
+/// its response through.
+#[tokio::test]
+async fn test_slow_upstream_within_timeout() {
+    init_logging();
+    let upstream = SlowServer::new(Duration::from_millis(500)).await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--upstream-timeout-secs", "2"]).await;
+
+    let response_text = balancebeam
+        .get("/slow")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("GET /slow HTTP/1.1"));
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// With --tls-cert and --tls-key, balancebeam should accept HTTPS from clients and forward the
+/// requests to the upstream as plain HTTP. (The certificates in tests/certs are signed by the
+/// throwaway CA in tests/certs/ca.pem.)
+#[tokio::test]
+async fn test_tls_termination() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--tls-cert",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/cert.pem"),
+            "--tls-key",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/key.pem"),
+        ],
+    )
+    .await;
+
+    let ca = reqwest::Certificate::from_pem(include_bytes!("certs/ca.pem")).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(ca)
+        .build()
+        .unwrap();
+    for i in 0..3 {
+        let path = format!("/secure-{}", i);
+        let response = client
+            .get(&format!("https://{}{}", balancebeam.address, path))
+            .header("x-sent-by", "balancebeam-tests")
+            .send()
+            .await
+            .expect("Error sending HTTPS request to balancebeam");
+        assert_eq!(response.status().as_u16(), 200);
+        let response_text = response.text().await.unwrap();
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+        assert!(response_text.contains("x-forwarded-for: 127.0.0.1"));
+    }
+
+    log::info!("Making sure plain HTTP isn't accepted");
+    assert!(balancebeam.get("/insecure").await.is_err());
+
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// balancebeam should be able to forward requests to upstreams that only speak HTTPS. The test
+/// upstream's certificate isn't signed by a real CA, so balancebeam should refuse to talk to it
+/// unless it's told not to check (with --insecure-upstream).
+#[tokio::test]
+async fn test_https_upstream() {
+    init_logging();
+    let upstream = EchoServer::new_with_tls().await;
+    // The certificate is for localhost, and an https:// upstream needs a hostname to check it
+    // against anyways
+    let upstream_address = format!(
+        "https://{}",
+        upstream.address.replace("127.0.0.1", "localhost")
+    );
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream_address], &["--insecure-upstream"]).await;
+    let verifying_balancebeam = BalanceBeam::new_with_args(&[&upstream_address], &[]).await;
+
+    log::info!("Sending a request to be forwarded over HTTPS");
+    let response_text = balancebeam
+        .get("/over-tls")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("GET /over-tls HTTP/1.1"));
+    assert!(response_text.contains("x-forwarded-for: 127.0.0.1"));
+
+    log::info!("Making sure the upstream's certificate is checked by default");
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&format!(
+            "http://{}/untrusted",
+            verifying_balancebeam.address
+        ))
+        .header("x-sent-by", "balancebeam-tests")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 502);
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// With caching turned on, a repeated GET should be answered from the cache (without the upstream
+/// seeing it), unless the upstream said not to store the response
+#[tokio::test]
+async fn test_response_caching() {
+    init_logging();
+    let upstream = CacheableServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--cache-max-size",
+            "100000",
+            "--active-health-check-interval",
+            "0",
+        ],
+    )
+    .await;
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(&format!("http://{}{}", balancebeam.address, path))
+            .header("x-sent-by", "balancebeam-tests")
+            .send()
+    };
+
+    log::info!("Sending the same request twice");
+    let first = get("/cached")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("x-cache").is_none());
+    let first_text = first.text().await.unwrap();
+    let second = get("/cached")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(second.headers().get("x-cache").unwrap(), "HIT");
+    assert_eq!(second.text().await.unwrap(), first_text);
+
+    log::info!("Sending a request whose response can't be stored, twice");
+    for _ in 0..2 {
+        let response = get("/no-store")
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response.headers().get("x-cache").is_none());
+    }
+
+    // /cached once, then /no-store both times
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// balancebeam should be able to get all of its settings from a --config file, with anything given
+/// on the command line taking precedence
+#[tokio::test]
+async fn test_config_file() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let config = format!(
+        "max_requests_per_minute = 1\n\n[[upstream]]\naddress = {:?}\nweight = 2\n",
+        upstream.address
+    );
+    let balancebeam = BalanceBeam::new_with_config(&config, &[]).await;
+    let overridden_balancebeam =
+        BalanceBeam::new_with_config(&config, &["--max-requests-per-minute", "0"]).await;
+    let client = reqwest::Client::new();
+    let get = |balancebeam: &BalanceBeam| {
+        client
+            .get(&format!("http://{}/configured", balancebeam.address))
+            .header("x-sent-by", "balancebeam-tests")
+            .send()
+    };
+
+    log::info!("Sending requests to balancebeam started from a config file");
+    let response = get(&balancebeam)
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("GET /configured HTTP/1.1"));
+    // The config file's rate limit applies
+    let response = get(&balancebeam)
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 429);
+
+    log::info!("Making sure command-line options override the config file");
+    for _ in 0..2 {
+        let response = get(&overridden_balancebeam)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// With --access-log, every request should get a line in the combined log format
+#[tokio::test]
+async fn test_access_log() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let log_path = std::env::temp_dir().join(format!(
+        "balancebeam-test-access-{}.log",
+        upstream.address.replace(|c: char| !c.is_ascii_digit(), "-")
+    ));
+    let _ = std::fs::remove_file(&log_path);
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &["--access-log", log_path.to_str().unwrap()],
+    )
+    .await;
+
+    log::info!("Sending a request to be logged");
+    let client = reqwest::Client::new();
+    let response_text = client
+        .get(&format!("http://{}/logged?page=2", balancebeam.address))
+        .header("referer", "http://example.com/")
+        .header("user-agent", "balancebeam-tests \"quoted\"")
+        .header("x-request-id", "logged-request")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam")
+        .text()
+        .await
+        .unwrap();
+
+    // The log is flushed once a second
+    delay_for(Duration::from_millis(1500)).await;
+    let log = std::fs::read_to_string(&log_path).expect("Access log wasn't written");
+    let _ = std::fs::remove_file(&log_path);
+    log::info!("Access log contents: {}", log);
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let line = lines[0];
+    assert!(line.starts_with("127.0.0.1 - - ["));
+    // The timestamp looks like [10/Oct/2000:13:55:36 -0700]
+    let timestamp = &line[line.find('[').unwrap() + 1..line.find(']').unwrap()];
+    assert_eq!(timestamp.len(), "10/Oct/2000:13:55:36 -0700".len());
+    assert!(line.ends_with(&format!(
+        "] \"GET /logged?page=2 HTTP/1.1\" 200 {} \"http://example.com/\" \
+        \"balancebeam-tests \\\"quoted\\\"\" \"logged-request\"",
+        response_text.len()
+    )));
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// The upstream should be told how the client reached balancebeam: X-Forwarded-Proto (http or
+/// https), X-Forwarded-Host (the Host the client asked for) and X-Real-IP (the client's address),
+/// replacing anything the client sent for those headers itself
+#[tokio::test]
+async fn test_forwarding_headers() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(&[&upstream.address], &[]).await;
+    let tls_balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--tls-cert",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/cert.pem"),
+            "--tls-key",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/certs/key.pem"),
+        ],
+    )
+    .await;
+
+    log::info!("Sending a request over HTTP");
+    let client = reqwest::Client::new();
+    let response_text = client
+        .get(&format!("http://{}/forwarded", balancebeam.address))
+        .header("x-real-ip", "10.0.0.1")
+        .header("x-forwarded-proto", "gopher")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam")
+        .text()
+        .await
+        .unwrap();
+    assert!(response_text.contains("x-forwarded-proto: http\n"));
+    assert!(response_text.contains(&format!("x-forwarded-host: {}\n", balancebeam.address)));
+    assert!(response_text.contains("x-real-ip: 127.0.0.1\n"));
+    assert!(!response_text.contains("10.0.0.1"));
+    assert!(!response_text.contains("gopher"));
+
+    log::info!("Sending a request over HTTPS");
+    let ca = reqwest::Certificate::from_pem(include_bytes!("certs/ca.pem")).unwrap();
+    let tls_client = reqwest::Client::builder()
+        .add_root_certificate(ca)
+        .build()
+        .unwrap();
+    let response_text = tls_client
+        .get(&format!("https://{}/forwarded", tls_balancebeam.address))
+        .send()
+        .await
+        .expect("Error sending HTTPS request to balancebeam")
+        .text()
+        .await
+        .unwrap();
+    assert!(response_text.contains("x-forwarded-proto: https\n"));
+    assert!(response_text.contains(&format!("x-forwarded-host: {}\n", tls_balancebeam.address)));
+    assert!(response_text.contains("x-real-ip: 127.0.0.1\n"));
+
+    assert_eq!(Box::new(upstream).stop().await, 2);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that a connection can be upgraded to a WebSocket through balancebeam, and that data
+/// then flows both ways over it.
+#[tokio::test]
+async fn test_websocket_upgrade() {
+    init_logging();
+    let upstream = UpgradeEchoServer::new().await;
+    let balancebeam = BalanceBeam::new(&[&upstream.address], None, None).await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending an upgrade request");
+    conn.write_all(
+        b"GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 101);
+    assert_eq!(response.headers.get("upgrade").unwrap(), "websocket");
+    assert_eq!(response.headers.get("connection").unwrap(), "upgrade");
+
+    log::info!("Sending a WebSocket frame over the upgraded connection");
+    // A masked text frame containing "Hello"
+    let frame = b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58";
+    conn.write_all(frame)
+        .await
+        .expect("Error sending frame to balancebeam");
+    let mut echoed = [0_u8; 11];
+    tokio::time::timeout(Duration::from_secs(5), conn.read_exact(&mut echoed))
+        .await
+        .expect("Timed out waiting for the frame to be echoed")
+        .expect("balancebeam closed the connection before echoing the frame");
+    assert_eq!(&echoed, frame);
+
+    drop(conn);
+    log::info!("Checking that the origin server upgraded 1 connection");
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that on SIGTERM, balancebeam stops taking new connections, but finishes the requests
+/// it's in the middle of before exiting.
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    init_logging();
+    let upstream = SlowServer::new(Duration::from_secs(2)).await;
+    let mut balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--shutdown-grace-secs", "10"]).await;
+
+    log::info!("Sending a request that the upstream will take a while to answer");
+    let address = balancebeam.address.clone();
+    let slow_request = tokio::spawn(async move {
+        reqwest::get(&format!("http://{}/slow", address))
+            .await?
+            .text()
+            .await
+    });
+    delay_for(Duration::from_millis(500)).await;
+
+    log::info!("Sending SIGTERM");
+    balancebeam.terminate();
+    delay_for(Duration::from_millis(500)).await;
+    assert!(
+        tokio::net::TcpStream::connect(&balancebeam.address)
+            .await
+            .is_err(),
+        "balancebeam is still accepting connections after SIGTERM"
+    );
+
+    log::info!("Checking that the slow request still completes");
+    let response_text = slow_request
+        .await
+        .unwrap()
+        .expect("The slow request failed after SIGTERM");
+    assert!(response_text.contains("GET /slow HTTP/1.1"));
+
+    let status = balancebeam
+        .wait_for_exit(Duration::from_secs(5))
+        .await
+        .expect("balancebeam didn't exit once its connections finished");
+    assert!(status.success());
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// HTTP/1.0 connections are closed after each response unless the client asks to keep them open
+/// (Connection: keep-alive). Make sure balancebeam follows those rules, and passes the requests on
+/// as HTTP/1.0.
+#[tokio::test]
+async fn test_http_10_connections() {
+    let (balancebeam, upstream) = setup().await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending HTTP/1.0 requests asking to keep the connection open");
+    for i in 0..2 {
+        conn.write_all(
+            format!(
+                "GET /keep-alive/{} HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+                i
+            )
+            .as_bytes(),
+        )
+        .await
+        .expect("Error sending request to balancebeam");
+        let response = read_raw_response(&mut conn)
+            .await
+            .expect("balancebeam closed the connection without responding");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("connection").unwrap(), "keep-alive");
+        assert!(response
+            .body
+            .contains(&format!("GET /keep-alive/{} HTTP/1.0", i)));
+    }
+
+    log::info!("Sending an HTTP/1.0 request without Connection: keep-alive");
+    conn.write_all(b"GET /close HTTP/1.0\r\n\r\n")
+        .await
+        .expect("Error sending request to balancebeam");
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 200);
+    assert_eq!(response.headers.get("connection").unwrap(), "close");
+    assert!(response.body.contains("GET /close HTTP/1.0"));
+    let mut buffer = [0_u8; 1];
+    let bytes_read = tokio::time::timeout(Duration::from_secs(2), conn.read(&mut buffer))
+        .await
+        .expect("balancebeam didn't close the connection after the response")
+        .unwrap_or(0);
+    assert_eq!(
+        bytes_read, 0,
+        "balancebeam sent more data after the response"
+    );
+
+    log::info!("Checking that the upstream connection was reused");
+    assert_eq!(upstream.connections_accepted(), 1);
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// Clients that send Expect: 100-continue wait for us to say so before sending the body. Make sure
+/// balancebeam tells them to go ahead, unless it's going to reject the body anyway.
+#[tokio::test]
+async fn test_expect_continue() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--max-body-size", "1000"]).await;
+
+    let mut conn = balancebeam.connect().await;
+    log::info!("Sending request headers with Expect: 100-continue");
+    conn.write_all(
+        b"POST /continue HTTP/1.1\r\nHost: localhost\r\nContent-Length: 12\r\n\
+        Expect: 100-continue\r\n\r\n",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+    let response = tokio::time::timeout(Duration::from_secs(2), read_raw_response(&mut conn))
+        .await
+        .expect("balancebeam didn't answer Expect: 100-continue")
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 100);
+
+    log::info!("Sending the body");
+    conn.write_all(b"Hello world!")
+        .await
+        .expect("Error sending request to balancebeam");
+    let response = read_raw_response(&mut conn)
+        .await
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 200);
+    assert!(response.body.contains("POST /continue HTTP/1.1"));
+    assert!(!response.body.contains("expect:"));
+    assert!(response.body.contains("\n\nHello world!"));
+
+    log::info!("Sending request headers for a body that is too big");
+    conn.write_all(
+        b"POST /too_big HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5000\r\n\
+        Expect: 100-continue\r\n\r\n",
+    )
+    .await
+    .expect("Error sending request to balancebeam");
+    let response = tokio::time::timeout(Duration::from_secs(2), read_raw_response(&mut conn))
+        .await
+        .expect("balancebeam didn't answer Expect: 100-continue")
+        .expect("balancebeam closed the connection without responding");
+    assert_eq!(response.status, 413);
+
+    log::info!("Checking that only the first request reached the origin server");
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// An upstream can end a response body by hanging up instead of sending a Content-Length. Make
+/// sure the client gets a Content-Length for it (since it can't go by the connection closing), and
+/// that balancebeam doesn't try to reuse the closed upstream connection.
+#[tokio::test]
+async fn test_close_delimited_response_body() {
+    init_logging();
+    let upstream = CloseDelimitedServer::new().await;
+    let balancebeam = BalanceBeam::new(&[&upstream.address], None, None).await;
+
+    for i in 0..2 {
+        log::info!(
+            "Sending request #{} to an upstream that hangs up to end the body",
+            i
+        );
+        let mut conn = balancebeam.connect().await;
+        let request_line = format!("GET /close_delimited_{} HTTP/1.1", i);
+        conn.write_all(format!("{}\r\nHost: localhost\r\n\r\n", request_line).as_bytes())
+            .await
+            .expect("Error sending request to balancebeam");
+        let response = tokio::time::timeout(Duration::from_secs(5), read_raw_response(&mut conn))
+            .await
+            .expect("balancebeam didn't finish sending the response")
+            .expect("balancebeam closed the connection without responding");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, request_line);
+        assert_eq!(response.headers["connection"], "close");
+    }
+
+    log::info!("Checking that the origin server received 2 requests");
+    assert_eq!(Box::new(upstream).stop().await, 2);
+
+    log::info!("All done :)");
+}
+
+/// A request with both Content-Length and Transfer-Encoding could be read two different ways,
+/// which is how requests get smuggled past proxies. Make sure balancebeam refuses it outright,
+/// along with any Transfer-Encoding that doesn't end in chunked (where the body's end can't be
+/// found at all).
+#[tokio::test]
+async fn test_conflicting_body_length() {
+    let (balancebeam, upstream) = setup().await;
+
+    let smuggling_requests: [&[u8]; 3] = [
+        b"POST /smuggle HTTP/1.1\r\nHost: localhost\r\nContent-Length: 34\r\n\
+        Transfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n",
+        b"POST /smuggle HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip\r\n\r\n\
+        GET /smuggled HTTP/1.1\r\n\r\n",
+        b"POST /smuggle HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked, identity\r\n\
+        \r\nGET /smuggled HTTP/1.1\r\n\r\n",
+    ];
+    for smuggling_request in smuggling_requests.iter() {
+        let mut conn = balancebeam.connect().await;
+        log::info!(
+            "Sending a request whose body length is ambiguous: {:?}",
+            String::from_utf8_lossy(smuggling_request)
+        );
+        conn.write_all(smuggling_request)
+            .await
+            .expect("Error sending request to balancebeam");
+        let response = read_raw_response(&mut conn)
+            .await
+            .expect("balancebeam closed the connection without responding");
+        assert_eq!(response.status, 400);
+        let mut buffer = [0_u8; 1];
+        let bytes_read = tokio::time::timeout(Duration::from_secs(2), conn.read(&mut buffer))
+            .await
+            .expect("balancebeam didn't close the connection after the response")
+            .unwrap_or(0);
+        assert_eq!(
+            bytes_read, 0,
+            "balancebeam sent more data after the response"
+        );
+    }
+
+    log::info!("Checking that nothing reached the origin server");
+    assert_eq!(Box::new(upstream).stop().await, 0);
+
+    log::info!("All done :)");
+}
+
+/// Upstreams can be given by hostname instead of IP address. Make sure balancebeam looks them up.
+#[tokio::test]
+async fn test_upstream_hostname() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let port = upstream.address.rsplit(':').next().unwrap();
+    let upstream_address = format!("localhost:{}", port);
+    // Look the hostname up again for every connection, to make sure that works too
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream_address], &["--dns-ttl-secs", "0"]).await;
+
+    log::info!(
+        "Sending requests to an upstream given as {}",
+        upstream_address
+    );
+    for i in 0..3 {
+        let path = format!("/hostname-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that every request is given an X-Request-Id that's passed to the upstream and sent
+/// back to the client, and that an ID the client sent itself is kept.
+#[tokio::test]
+async fn test_request_id() {
+    let (balancebeam, upstream) = setup().await;
+    let client = reqwest::Client::new();
+
+    log::info!("Sending a request without an ID");
+    let response = client
+        .get(&format!("http://{}/request-id", balancebeam.address))
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .expect("Response had no X-Request-Id")
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(request_id.len(), 32);
+    let response_text = response.text().await.unwrap();
+    assert!(response_text.contains(&format!("x-request-id: {}\n", request_id)));
+
+    log::info!("Sending another request, which should get a different ID");
+    let response = client
+        .get(&format!("http://{}/request-id", balancebeam.address))
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_ne!(response.headers()["x-request-id"], request_id.as_str());
+
+    log::info!("Sending a request with its own ID");
+    let response = client
+        .get(&format!("http://{}/request-id", balancebeam.address))
+        .header("x-request-id", "my-id")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.headers()["x-request-id"], "my-id");
+    let response_text = response.text().await.unwrap();
+    assert!(response_text.contains("x-request-id: my-id\n"));
+
+    assert_eq!(Box::new(upstream).stop().await, 3);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that in maintenance mode, requests get a 503 with a Retry-After, except for the
+/// health check path, which still goes through to the upstream.
+#[tokio::test]
+async fn test_maintenance_mode() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--maintenance-health-path",
+            "/healthz",
+            "--maintenance-retry-after-secs",
+            "120",
+        ],
+    )
+    .await;
+    let client = reqwest::Client::new();
+
+    log::info!("Entering maintenance mode");
+    balancebeam.toggle_maintenance().await;
+    let response = client
+        .get(&format!("http://{}/page", balancebeam.address))
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["retry-after"], "120");
+    let response_text = balancebeam
+        .get("/healthz")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("GET /healthz HTTP/1.1"));
+
+    log::info!("Leaving maintenance mode");
+    balancebeam.toggle_maintenance().await;
+    let response_text = balancebeam
+        .get("/page")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("GET /page HTTP/1.1"));
+
+    assert_eq!(Box::new(upstream).stop().await, 2);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that with --log-format json, each log message is a JSON object, and that the one for
+/// a finished request has the request's details.
+#[tokio::test]
+async fn test_json_logging() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--log-format", "json"]).await;
+
+    log::info!("Sending a request");
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&format!("http://{}/json-logged", balancebeam.address))
+        .header("x-request-id", "json-request")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Give the log line a moment to come through
+    delay_for(Duration::from_millis(500)).await;
+    let lines = balancebeam.stderr_lines();
+    assert!(!lines.is_empty());
+    let events: Vec<serde_json::Value> = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).expect("Log line wasn't JSON"))
+        .collect();
+    for event in &events {
+        assert!(event["timestamp"].is_string());
+        assert!(event["level"].is_string());
+        assert!(event["message"].is_string());
+    }
+    let request_event = events
+        .iter()
+        .find(|event| event["request_id"] == "json-request")
+        .expect("No log message for the request");
+    assert_eq!(request_event["level"], "INFO");
+    assert_eq!(request_event["client_ip"], "127.0.0.1");
+    assert_eq!(request_event["upstream"], upstream.address.as_str());
+    assert_eq!(request_event["status"], 200);
+    assert!(request_event["latency_ms"].is_u64());
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// Make sure that --add-response-header and --remove-response-header are applied to responses, and
+/// that header names are matched regardless of case.
+#[tokio::test]
+async fn test_response_header_rewriting() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--add-response-header",
+            "Server: balancebeam",
+            "--add-response-header",
+            "Strict-Transport-Security:max-age=31536000",
+            // hyper puts a Date header on every response the upstream sends
+            "--remove-response-header",
+            "DATE",
+        ],
+    )
+    .await;
+
+    log::info!("Sending a request");
+    let response = reqwest::get(&format!("http://{}/rewritten", balancebeam.address))
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["server"], "balancebeam");
+    assert_eq!(
+        response.headers()["strict-transport-security"],
+        "max-age=31536000"
+    );
+    assert!(!response.headers().contains_key("date"));
+    let response_text = response.text().await.unwrap();
+    assert!(response_text.contains("GET /rewritten HTTP/1.1"));
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
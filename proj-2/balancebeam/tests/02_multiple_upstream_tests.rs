@@ -1,8 +1,13 @@
 mod common;
 
-use common::{init_logging, BalanceBeam, EchoServer, ErrorServer, Server};
+use common::{
+    init_logging, read_raw_response, BalanceBeam, EchoServer, ErrorServer, HangUpServer,
+    HealthzServer, Server, SlowServer,
+};
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
 use tokio::time::delay_for;
 
 async fn setup_with_params(
@@ -80,6 +85,156 @@ async fn test_load_distribution() {
     log::info!("All done :)");
 }
 
+/// Give one upstream three times the weight of the other, and ensure it gets about three times as
+/// many requests
+#[tokio::test]
+async fn test_weighted_load_distribution() {
+    init_logging();
+    let n_requests = 80;
+    let upstreams = vec![EchoServer::new().await, EchoServer::new().await];
+    let light_upstream = format!("{}@1", upstreams[0].address);
+    let heavy_upstream = format!("{}@3", upstreams[1].address);
+    let balancebeam = BalanceBeam::new(&[&light_upstream, &heavy_upstream], None, None).await;
+
+    for i in 0..n_requests {
+        let path = format!("/request-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+
+    let mut request_counters = Vec::new();
+    for upstream in upstreams {
+        request_counters.push(Box::new(upstream).stop().await);
+    }
+    log::info!(
+        "Number of requests received by each upstream: {:?}",
+        request_counters
+    );
+    let ratio = request_counters[1] as f64 / request_counters[0] as f64;
+    assert!(
+        (ratio - 3.0).abs() < 0.5,
+        "Expected the weight-3 upstream to get about 3x as many requests, but the ratio was {}",
+        ratio
+    );
+
+    log::info!("All done :)");
+}
+
+/// With least-connections balancing, connections should pile up less on an upstream that is slow
+/// to respond: while its connections are still busy, new ones should go to the fast upstream
+#[tokio::test]
+async fn test_least_connections() {
+    init_logging();
+    let n_requests = 20;
+    let slow_upstream = SlowServer::new(Duration::from_secs(2)).await;
+    let fast_upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&slow_upstream.address, &fast_upstream.address],
+        &["--lb-algorithm", "least-connections"],
+    )
+    .await;
+
+    // Send the requests concurrently, a little apart from each other, so that there are requests
+    // in flight whenever a new one arrives. Share one client (creating a client is slow enough to
+    // hold up the upstreams, which run on this thread too), but give each request its own
+    // connection so that each one gets balanced.
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(0)
+        .build()
+        .unwrap();
+    let mut tasks = Vec::new();
+    for i in 0..n_requests {
+        let url = format!("http://{}/request-{}", balancebeam.address, i);
+        let client = client.clone();
+        tasks.push(tokio::spawn(async move {
+            let response_text = client
+                .get(&url)
+                .send()
+                .await
+                .expect("Error sending request to balancebeam")
+                .text()
+                .await
+                .expect("Error reading response from balancebeam");
+            assert!(response_text.contains(&format!("GET /request-{} HTTP/1.1", i)));
+        }));
+        delay_for(Duration::from_millis(50)).await;
+    }
+    for task in tasks {
+        task.await.expect("Task panicked");
+    }
+
+    let slow_count = Box::new(slow_upstream).stop().await;
+    let fast_count = Box::new(fast_upstream).stop().await;
+    log::info!(
+        "Slow upstream received {} requests, fast upstream received {}",
+        slow_count,
+        fast_count
+    );
+    assert_eq!(slow_count + fast_count, n_requests);
+    assert!(
+        fast_count > 2 * slow_count,
+        "Expected most requests to go to the fast upstream"
+    );
+
+    log::info!("All done :)");
+}
+
+/// With ip-hash balancing, every request from one client should go to the same upstream, until
+/// that upstream dies and the client moves to another one
+#[tokio::test]
+async fn test_ip_hash() {
+    init_logging();
+    let n_requests = 10;
+    let mut upstreams = vec![EchoServer::new().await, EchoServer::new().await];
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstreams[0].address, &upstreams[1].address],
+        &["--lb-algorithm", "ip-hash"],
+    )
+    .await;
+
+    // All of our requests come from 127.0.0.1
+    log::info!("Sending some requests from the same client");
+    for i in 0..n_requests {
+        let path = format!("/request-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+
+    log::info!("Killing one of the upstream servers");
+    let killed_count = Box::new(upstreams.pop().unwrap()).stop().await;
+    log::info!("Sending some more requests from the same client");
+    for i in 0..n_requests {
+        let path = format!("/after-kill-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+    let remaining_count = Box::new(upstreams.pop().unwrap()).stop().await;
+
+    // Either the client was pinned to the upstream we killed (and moved over to the other one), or
+    // it was pinned to the other one all along
+    log::info!(
+        "Killed upstream received {} requests, remaining upstream received {}",
+        killed_count,
+        remaining_count
+    );
+    assert!(
+        (killed_count, remaining_count) == (n_requests, n_requests)
+            || (killed_count, remaining_count) == (0, 2 * n_requests),
+        "Requests from the same client were not all sent to the same upstream"
+    );
+
+    log::info!("All done :)");
+}
+
 async fn try_failover(balancebeam: &BalanceBeam, upstreams: &mut Vec<Box<dyn Server>>) {
     // Send some initial requests. Everything should work
     log::info!("Sending some initial requests. These should definitely work.");
@@ -190,6 +345,61 @@ async fn test_active_health_checks_check_http_status() {
     }
 }
 
+/// Point the active health checks at a dedicated endpoint, and make sure an upstream that is fine
+/// there (with a 204, which counts as healthy with 200-299) stays in rotation even though it fails
+/// at /. A second balancebeam using the default check of / should take it out of rotation.
+#[tokio::test]
+async fn test_active_health_checks_custom_path() {
+    init_logging();
+    let upstream = HealthzServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--active-health-check-interval",
+            "1",
+            "--health-check-path",
+            "/healthz",
+            "--health-check-expected-status",
+            "200-299",
+        ],
+    )
+    .await;
+    let default_balancebeam = BalanceBeam::new(&[&upstream.address], Some(1), None).await;
+
+    log::info!("Waiting for health checks to run...");
+    delay_for(Duration::from_secs(3)).await;
+
+    log::info!("Sending requests through the balancebeam that checks /healthz");
+    let client = reqwest::Client::new();
+    for i in 0..4 {
+        let response = client
+            .get(&format!("http://{}/request-{}", balancebeam.address, i))
+            .send()
+            .await
+            .expect("Error sending request to balancebeam");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    log::info!("Sending a request through the balancebeam that checks /");
+    let response = client
+        .get(&format!(
+            "http://{}/request-default",
+            default_balancebeam.address
+        ))
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 502);
+
+    let num_requests_received = Box::new(upstream).stop().await;
+    assert_eq!(
+        num_requests_received, 4,
+        "Upstream server did not receive the expected number of requests"
+    );
+
+    log::info!("All done :)");
+}
+
 /// Make sure active health checks restore upstreams that were previously failed but are now
 /// working again:
 ///
@@ -240,6 +450,152 @@ async fn test_active_health_checks_restore_failed_upstream() {
     log::info!("All done :)");
 }
 
+/// Sends n_requests requests to balancebeam, making sure they all succeed
+async fn send_requests(balancebeam: &BalanceBeam, prefix: &str, n_requests: usize) {
+    for i in 0..n_requests {
+        let path = format!("/{}-{}", prefix, i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+}
+
+/// If the upstream a request is sent to hangs up on it without responding, the request should be
+/// retried with another upstream, and the client should never know anything went wrong. Without
+/// --max-retries, the client should get a 502 instead. (With least-connections balancing and one
+/// request at a time, the broken upstream always gets the first try, since it's listed first.)
+#[tokio::test]
+async fn test_retry_with_different_upstream() {
+    init_logging();
+    let broken_upstream = HangUpServer::new().await;
+    let upstream = EchoServer::new().await;
+    let upstream_addresses = [broken_upstream.address.as_str(), upstream.address.as_str()];
+    let retrying_balancebeam = BalanceBeam::new_with_args(
+        &upstream_addresses,
+        &["--lb-algorithm", "least-connections", "--max-retries", "1"],
+    )
+    .await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &upstream_addresses,
+        &["--lb-algorithm", "least-connections"],
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+    log::info!("Sending a request that has to be retried");
+    let response = client
+        .get(&format!("http://{}/retried", retrying_balancebeam.address))
+        .header("x-sent-by", "balancebeam-tests")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("GET /retried HTTP/1.1"));
+
+    log::info!("Sending a request without retries enabled");
+    let response = client
+        .get(&format!("http://{}/not-retried", balancebeam.address))
+        .header("x-sent-by", "balancebeam-tests")
+        .send()
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 502);
+
+    assert_eq!(Box::new(broken_upstream).stop().await, 2);
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// An upstream whose hostname can't be looked up should just be treated as down, with requests
+/// going to the other upstreams instead.
+#[tokio::test]
+async fn test_unresolvable_upstream() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new(
+        &["does-not-exist.invalid:80", &upstream.address],
+        None,
+        None,
+    )
+    .await;
+
+    for i in 0..4 {
+        let path = format!("/unresolvable-{}", i);
+        let response_text = balancebeam
+            .get(&path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", path)));
+    }
+
+    assert_eq!(Box::new(upstream).stop().await, 4);
+
+    log::info!("All done :)");
+}
+
+/// With --max-failures 2, an upstream should only be marked dead after failing twice in a row. This
+/// uses least-connections balancing, since with one request at a time it always tries the first
+/// live upstream, so each request fails exactly once while the flaky upstream is down:
+///
+/// * Kill the flaky upstream, and let it fail once
+/// * Bring it back; it should still be in rotation, and its failure count should reset
+/// * Kill it again and let it fail once more; it still shouldn't be marked dead
+/// * Kill it again and let it fail twice; now it should be taken out of rotation
+#[tokio::test]
+async fn test_max_failures() {
+    init_logging();
+    let flaky_upstream = EchoServer::new().await;
+    let steady_upstream = EchoServer::new().await;
+    let flaky_ip = flaky_upstream.address.clone();
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&flaky_ip, &steady_upstream.address],
+        &["--lb-algorithm", "least-connections", "--max-failures", "2"],
+    )
+    .await;
+
+    log::info!("Killing the flaky upstream and letting it fail once");
+    Box::new(flaky_upstream).stop().await;
+    send_requests(&balancebeam, "first-failure", 1).await;
+    let flaky_upstream = EchoServer::new_at_address(flaky_ip.clone()).await;
+    send_requests(&balancebeam, "first-restore", 2).await;
+    assert_eq!(
+        Box::new(flaky_upstream).stop().await,
+        2,
+        "Upstream was taken out of rotation after failing only once"
+    );
+
+    log::info!("Letting the flaky upstream fail once more, after it had recovered");
+    send_requests(&balancebeam, "second-failure", 1).await;
+    let flaky_upstream = EchoServer::new_at_address(flaky_ip.clone()).await;
+    send_requests(&balancebeam, "second-restore", 2).await;
+    assert_eq!(
+        Box::new(flaky_upstream).stop().await,
+        2,
+        "Upstream was taken out of rotation, but its failures weren't in a row"
+    );
+
+    log::info!("Letting the flaky upstream fail twice in a row");
+    send_requests(&balancebeam, "third-failure", 2).await;
+    let flaky_upstream = EchoServer::new_at_address(flaky_ip).await;
+    send_requests(&balancebeam, "third-restore", 2).await;
+    assert_eq!(
+        Box::new(flaky_upstream).stop().await,
+        0,
+        "Upstream failed twice in a row but was never taken out of rotation"
+    );
+
+    assert_eq!(Box::new(steady_upstream).stop().await, 6);
+
+    log::info!("All done :)");
+}
+
 /// Enable rate limiting and ensure that requests fail after sending more than the threshold
 #[tokio::test]
 async fn test_rate_limiting() {
@@ -294,3 +650,407 @@ async fn test_rate_limiting() {
 
     log::info!("All done :)");
 }
+
+/// Rate limits should apply to each client separately: one client using up its allowance shouldn't
+/// affect anyone else. (All of our requests come from 127.0.0.1, so we pretend to be different
+/// clients using X-Forwarded-For, as if balancebeam were sitting behind another proxy.)
+#[tokio::test]
+async fn test_rate_limiting_per_client() {
+    init_logging();
+    let rate_limit_threshold = 3;
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--max-requests-per-minute",
+            &rate_limit_threshold.to_string(),
+        ],
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+    let send_request_from = |client_ip: &'static str, path: String| {
+        client
+            .get(&format!("http://{}{}", balancebeam.address, path))
+            .header("x-sent-by", "balancebeam-tests")
+            .header("x-forwarded-for", client_ip)
+            .send()
+    };
+    for client_ip in &["10.0.0.1", "10.0.0.2"] {
+        log::info!("Sending requests from {}", client_ip);
+        for i in 0..rate_limit_threshold {
+            let response = send_request_from(client_ip, format!("/request-{}", i))
+                .await
+                .expect("Error sending request to balancebeam");
+            assert_eq!(response.status().as_u16(), 200);
+        }
+        let response = send_request_from(client_ip, "/overboard".to_string())
+            .await
+            .expect("Error sending rate limited request to balancebeam");
+        assert_eq!(response.status().as_u16(), 429);
+    }
+
+    log::info!("Ensuring the rate limited requests didn't go through to the upstream server");
+    assert_eq!(Box::new(upstream).stop().await, 2 * rate_limit_threshold);
+
+    log::info!("All done :)");
+}
+
+/// A rate limited client should be told how long to wait (in seconds) before trying again
+#[tokio::test]
+async fn test_rate_limiting_retry_after() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--max-requests-per-minute", "1"]).await;
+
+    balancebeam
+        .get("/request-0")
+        .await
+        .expect("Error sending request to balancebeam");
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&format!("http://{}/overboard", balancebeam.address))
+        .header("x-sent-by", "balancebeam-tests")
+        .send()
+        .await
+        .expect("Error sending rate limited request to balancebeam");
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("429 response is missing a Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .expect("Retry-After should be a number of seconds");
+    assert!(
+        (1..=60).contains(&retry_after),
+        "Retry-After of {} seconds doesn't fit the one-minute window",
+        retry_after
+    );
+
+    Box::new(upstream).stop().await;
+
+    log::info!("All done :)");
+}
+
+/// The rate limit should hold over any span of the window, not just within fixed blocks of time
+/// (which would let a client squeeze in twice its allowance around the end of one block and the
+/// start of the next)
+#[tokio::test]
+async fn test_rate_limiting_sliding_window() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &[
+            "--max-requests-per-minute",
+            "3",
+            "--rate-limit-window-secs",
+            "2",
+        ],
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+    let send_request = |path: &'static str| {
+        client
+            .get(&format!("http://{}{}", balancebeam.address, path))
+            .header("x-sent-by", "balancebeam-tests")
+            .send()
+    };
+    let start = Instant::now();
+    let wait_until = |offset: Duration| delay_for((start + offset) - Instant::now());
+
+    for path in &["/at-0s-0", "/at-0s-1"] {
+        assert_eq!(send_request(path).await.unwrap().status().as_u16(), 200);
+    }
+    wait_until(Duration::from_secs(1)).await;
+    assert_eq!(
+        send_request("/at-1s-0").await.unwrap().status().as_u16(),
+        200
+    );
+    assert_eq!(
+        send_request("/at-1s-1").await.unwrap().status().as_u16(),
+        429
+    );
+
+    log::info!(
+        "Waiting for the first requests to leave the window. The one from 1s in should still \
+        count."
+    );
+    wait_until(Duration::from_millis(2300)).await;
+    for path in &["/at-2s-0", "/at-2s-1"] {
+        assert_eq!(send_request(path).await.unwrap().status().as_u16(), 200);
+    }
+    assert_eq!(
+        send_request("/at-2s-2").await.unwrap().status().as_u16(),
+        429
+    );
+
+    assert_eq!(Box::new(upstream).stop().await, 5);
+
+    log::info!("All done :)");
+}
+
+/// With --max-connections-per-ip, a client that already has that many connections open should
+/// have any more refused, until it closes one of them
+#[tokio::test]
+async fn test_max_connections_per_ip() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--max-connections-per-ip", "2"]).await;
+
+    async fn send_request(conn: &mut TcpStream) -> Option<u16> {
+        conn.write_all(b"GET /conn HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .ok()?;
+        Some(read_raw_response(conn).await?.status)
+    }
+
+    log::info!("Opening as many connections as we're allowed");
+    let mut conns = Vec::new();
+    for _ in 0..2 {
+        let mut conn = balancebeam.connect().await;
+        assert_eq!(send_request(&mut conn).await, Some(200));
+        conns.push(conn);
+    }
+
+    log::info!("Opening one more connection. It should be closed straight away");
+    let mut extra_conn = balancebeam.connect().await;
+    let mut buffer = [0_u8; 1];
+    let bytes_read = tokio::time::timeout(Duration::from_secs(2), extra_conn.read(&mut buffer))
+        .await
+        .expect("balancebeam didn't close the connection over the limit")
+        .unwrap_or(0);
+    assert_eq!(bytes_read, 0);
+
+    log::info!("Closing a connection. Now another one should be allowed");
+    drop(conns.pop());
+    delay_for(Duration::from_millis(200)).await;
+    let mut conn = balancebeam.connect().await;
+    assert_eq!(send_request(&mut conn).await, Some(200));
+    assert_eq!(send_request(&mut conns[0]).await, Some(200));
+
+    assert_eq!(Box::new(upstream).stop().await, 4);
+
+    log::info!("All done :)");
+}
+
+/// --allow-ip should restrict balancebeam to serving clients in the given ranges
+#[tokio::test]
+async fn test_allow_ip() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let allowing_balancebeam =
+        BalanceBeam::new_with_args(&[&upstream.address], &["--allow-ip", "127.0.0.0/8"]).await;
+    let refusing_balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &["--allow-ip", "10.0.0.0/8", "--allow-ip", "::1"],
+    )
+    .await;
+
+    log::info!("Sending a request from an allowed IP");
+    let response_text = allowing_balancebeam
+        .get("/allowed")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert!(response_text.contains("GET /allowed HTTP/1.1"));
+
+    log::info!("Sending a request from an IP that isn't allowed");
+    assert!(
+        refusing_balancebeam.get("/not-allowed").await.is_err(),
+        "balancebeam served a client that wasn't in --allow-ip"
+    );
+
+    assert_eq!(Box::new(upstream).stop().await, 1);
+
+    log::info!("All done :)");
+}
+
+/// --deny-ip should refuse clients in the given ranges, even if --allow-ip includes them
+#[tokio::test]
+async fn test_deny_ip() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let balancebeam = BalanceBeam::new_with_args(
+        &[&upstream.address],
+        &["--allow-ip", "127.0.0.0/8", "--deny-ip", "127.0.0.1/32"],
+    )
+    .await;
+
+    log::info!("Connecting from a denied IP");
+    let mut conn = balancebeam.connect().await;
+    let mut buffer = [0_u8; 1];
+    let bytes_read = tokio::time::timeout(Duration::from_secs(2), conn.read(&mut buffer))
+        .await
+        .expect("balancebeam didn't close the connection from a denied IP")
+        .unwrap_or(0);
+    assert_eq!(bytes_read, 0);
+    assert!(balancebeam.get("/denied").await.is_err());
+
+    assert_eq!(Box::new(upstream).stop().await, 0);
+
+    log::info!("All done :)");
+}
+
+/// Sending balancebeam a SIGHUP should make it reload its config file, so an upstream added there
+/// should start getting requests, without a restart. If the new config is invalid, balancebeam
+/// should keep going with the old one.
+#[tokio::test]
+async fn test_reload_config() {
+    init_logging();
+    let upstream = EchoServer::new().await;
+    let new_upstream = EchoServer::new().await;
+    let config_for = |upstreams: &[&EchoServer]| {
+        let mut config = "active_health_check_interval = 0\n".to_string();
+        for upstream in upstreams {
+            config.push_str(&format!(
+                "\n[[upstream]]\naddress = {:?}\n",
+                upstream.address
+            ));
+        }
+        config
+    };
+    let balancebeam = BalanceBeam::new_with_config(&config_for(&[&upstream]), &[]).await;
+
+    log::info!("Sending requests with one upstream configured");
+    send_requests(&balancebeam, "before-reload", 4).await;
+
+    log::info!("Adding an upstream to the config and reloading");
+    balancebeam
+        .reload_config(&config_for(&[&upstream, &new_upstream]))
+        .await;
+    send_requests(&balancebeam, "after-reload", 4).await;
+
+    log::info!("Reloading an invalid config");
+    balancebeam
+        .reload_config("[[upstream]]\nweight = 1\n")
+        .await;
+    send_requests(&balancebeam, "after-bad-reload", 4).await;
+
+    // Round robin splits the requests since the first reload evenly
+    assert_eq!(Box::new(upstream).stop().await, 8);
+    assert_eq!(Box::new(new_upstream).stop().await, 4);
+
+    log::info!("All done :)");
+}
+
+/// With pools and host routes in the config file, each request should go to the pool for the host
+/// it's for, and requests for other hosts should get a 404 (since there's no default pool)
+#[tokio::test]
+async fn test_host_routing() {
+    init_logging();
+    let api_upstream = EchoServer::new().await;
+    let www_upstream = EchoServer::new().await;
+    let config = format!(
+        "active_health_check_interval = 0\n\
+        \n\
+        [[pools.api.upstream]]\n\
+        address = {:?}\n\
+        \n\
+        [[pools.www.upstream]]\n\
+        address = {:?}\n\
+        \n\
+        [hosts]\n\
+        \"api.example.com\" = \"api\"\n\
+        \"www.example.com\" = \"www\"\n",
+        api_upstream.address, www_upstream.address
+    );
+    let balancebeam = BalanceBeam::new_with_config(&config, &[]).await;
+    let client = reqwest::Client::new();
+    let get = |host: &str| {
+        client
+            .get(&format!("http://{}/routed", balancebeam.address))
+            .header("host", host)
+            .send()
+    };
+
+    log::info!("Sending requests for each host");
+    for host in &[
+        "api.example.com",
+        "www.example.com:1100",
+        "WWW.example.com",
+        "api.example.com",
+    ] {
+        let response = get(host)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert_eq!(response.status().as_u16(), 200);
+        let response_text = response.text().await.unwrap();
+        assert!(response_text.contains(&format!("host: {}\n", host)));
+    }
+
+    log::info!("Sending a request for a host with no route");
+    let response = get("other.example.com")
+        .await
+        .expect("Error sending request to balancebeam");
+    assert_eq!(response.status().as_u16(), 404);
+
+    assert_eq!(Box::new(api_upstream).stop().await, 2);
+    assert_eq!(Box::new(www_upstream).stop().await, 2);
+
+    log::info!("All done :)");
+}
+
+/// With path-prefix routes in the config file, each request should go to the pool for the
+/// longest prefix its path matches, with the prefix stripped off if the route says so
+#[tokio::test]
+async fn test_path_routing() {
+    init_logging();
+    let api_upstream = EchoServer::new().await;
+    let web_upstream = EchoServer::new().await;
+    let config = format!(
+        "active_health_check_interval = 0\n\
+        \n\
+        [[pools.api.upstream]]\n\
+        address = {:?}\n\
+        \n\
+        [[pools.web.upstream]]\n\
+        address = {:?}\n\
+        \n\
+        [[routes]]\n\
+        prefix = \"/api\"\n\
+        pool = \"api\"\n\
+        strip_prefix = true\n\
+        \n\
+        [[routes]]\n\
+        prefix = \"/api/legacy/\"\n\
+        pool = \"web\"\n\
+        \n\
+        [[routes]]\n\
+        prefix = \"/web\"\n\
+        pool = \"web\"\n",
+        api_upstream.address, web_upstream.address
+    );
+    let balancebeam = BalanceBeam::new_with_config(&config, &[]).await;
+
+    log::info!("Sending requests that match each route");
+    for (path, forwarded_path) in &[
+        ("/api/foo?x=1", "/foo?x=1"),
+        ("/api", "/"),
+        ("/web/bar", "/web/bar"),
+        ("/api/legacy/baz", "/api/legacy/baz"),
+    ] {
+        let response_text = balancebeam
+            .get(path)
+            .await
+            .expect("Error sending request to balancebeam");
+        assert!(response_text.contains(&format!("GET {} HTTP/1.1", forwarded_path)));
+    }
+
+    log::info!("Sending requests that don't match any route");
+    for path in &["/apis", "/other"] {
+        let response = reqwest::get(&format!("http://{}{}", balancebeam.address, path))
+            .await
+            .expect("Error sending request to balancebeam");
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    assert_eq!(Box::new(api_upstream).stop().await, 2);
+    assert_eq!(Box::new(web_upstream).stop().await, 2);
+
+    log::info!("All done :)");
+}
@@ -1,6 +1,12 @@
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
 use rand::Rng;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::TcpStream;
 use tokio::process::{Child, Command};
 use tokio::time::delay_for;
 
@@ -8,6 +14,12 @@ pub struct BalanceBeam {
     #[allow(dead_code)]
     child: Child, // process is killed when dropped (Command::kill_on_drop)
     pub address: String,
+    /// The config file balancebeam was started with, if it was started by new_with_config. It's
+    /// deleted once balancebeam is dropped.
+    #[allow(dead_code)]
+    pub config_path: Option<PathBuf>,
+    /// Everything balancebeam has written to stderr so far, line by line
+    stderr_lines: Arc<Mutex<Vec<String>>>,
 }
 
 impl BalanceBeam {
@@ -24,21 +36,100 @@ impl BalanceBeam {
         active_health_check_interval: Option<usize>,
         max_requests_per_minute: Option<usize>,
     ) -> BalanceBeam {
-        let mut rng = rand::thread_rng();
-        let address = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let mut args = Vec::new();
+        if let Some(active_health_check_interval) = active_health_check_interval {
+            args.push("--active-health-check-interval".to_string());
+            args.push(active_health_check_interval.to_string());
+        }
+        if let Some(max_requests_per_minute) = max_requests_per_minute {
+            args.push("--max-requests-per-minute".to_string());
+            args.push(max_requests_per_minute.to_string());
+        }
+        let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+        BalanceBeam::new_with_args(upstreams, &args).await
+    }
+
+    /// Starts balancebeam with the given upstreams, passing any other command-line arguments
+    /// through as-is
+    pub async fn new_with_args(upstreams: &[&str], args: &[&str]) -> BalanceBeam {
+        let address = BalanceBeam::random_address();
         let mut cmd = Command::new(BalanceBeam::target_bin_path());
         cmd.arg("--bind").arg(&address);
         for upstream in upstreams {
             cmd.arg("--upstream").arg(upstream);
         }
-        if let Some(active_health_check_interval) = active_health_check_interval {
-            cmd.arg("--active-health-check-interval")
-                .arg(active_health_check_interval.to_string());
-        }
-        if let Some(max_requests_per_minute) = max_requests_per_minute {
-            cmd.arg("--max-requests-per-minute")
-                .arg(max_requests_per_minute.to_string());
-        }
+        cmd.args(args);
+        BalanceBeam::start(cmd, address, None).await
+    }
+
+    /// Starts balancebeam with nothing but a config file (plus any other command-line arguments
+    /// given). The config is written to a temporary file, with a bind address added to the start
+    /// of it.
+    #[allow(dead_code)]
+    pub async fn new_with_config(config: &str, args: &[&str]) -> BalanceBeam {
+        let address = BalanceBeam::random_address();
+        let config_path = std::env::temp_dir().join(format!(
+            "balancebeam-test-{}.toml",
+            address.replace(|c: char| !c.is_ascii_digit(), "-")
+        ));
+        BalanceBeam::write_config(&config_path, &address, config);
+        let mut cmd = Command::new(BalanceBeam::target_bin_path());
+        cmd.arg("--config").arg(&config_path);
+        cmd.args(args);
+        BalanceBeam::start(cmd, address, Some(config_path)).await
+    }
+
+    /// Replaces the config file balancebeam was started with (see new_with_config), then sends
+    /// balancebeam a SIGHUP to tell it to reload the file
+    #[allow(dead_code)]
+    pub async fn reload_config(&self, config: &str) {
+        let config_path = self
+            .config_path
+            .as_ref()
+            .expect("balancebeam wasn't started with a config file");
+        BalanceBeam::write_config(config_path, &self.address, config);
+        signal::kill(Pid::from_raw(self.child.id() as i32), Signal::SIGHUP)
+            .expect("Could not send SIGHUP to balancebeam");
+        // Give it a moment to reload
+        delay_for(Duration::from_millis(500)).await;
+    }
+
+    /// Sends balancebeam a SIGUSR1, switching maintenance mode on (or back off)
+    #[allow(dead_code)]
+    pub async fn toggle_maintenance(&self) {
+        signal::kill(Pid::from_raw(self.child.id() as i32), Signal::SIGUSR1)
+            .expect("Could not send SIGUSR1 to balancebeam");
+        // Give it a moment to notice
+        delay_for(Duration::from_millis(500)).await;
+    }
+
+    /// Sends balancebeam a SIGTERM, telling it to shut down once its open connections finish
+    #[allow(dead_code)]
+    pub fn terminate(&self) {
+        signal::kill(Pid::from_raw(self.child.id() as i32), Signal::SIGTERM)
+            .expect("Could not send SIGTERM to balancebeam");
+    }
+
+    /// Waits up to `timeout` for balancebeam to exit, returning its exit status if it did
+    #[allow(dead_code)]
+    pub async fn wait_for_exit(&mut self, timeout: Duration) -> Option<ExitStatus> {
+        tokio::time::timeout(timeout, &mut self.child)
+            .await
+            .ok()
+            .map(|status| status.expect("Could not wait for balancebeam to exit"))
+    }
+
+    fn write_config(config_path: &Path, address: &str, config: &str) {
+        std::fs::write(config_path, format!("bind = {:?}\n{}", address, config))
+            .expect("Could not write config file");
+    }
+
+    fn random_address() -> String {
+        let mut rng = rand::thread_rng();
+        format!("127.0.0.1:{}", rng.gen_range(1024, 65535))
+    }
+
+    async fn start(mut cmd: Command, address: String, config_path: Option<PathBuf>) -> BalanceBeam {
         cmd.kill_on_drop(true);
         cmd.stdout(std::process::Stdio::piped());
         cmd.stderr(std::process::Stdio::piped());
@@ -68,6 +159,8 @@ impl BalanceBeam {
             .stderr
             .take()
             .expect("Child process somehow missing stderr pipe!");
+        let stderr_lines = Arc::new(Mutex::new(Vec::new()));
+        let stderr_lines_ref = stderr_lines.clone();
         tokio::spawn(async move {
             let mut stderr_reader = BufReader::new(stderr).lines();
             while let Some(line) = stderr_reader
@@ -76,12 +169,24 @@ impl BalanceBeam {
                 .expect("I/O error reading from child stderr")
             {
                 println!("Balancebeam output: {}", line);
+                stderr_lines_ref.lock().unwrap().push(line);
             }
         });
 
         // Hack: wait for executable to start running
         delay_for(Duration::from_secs(1)).await;
-        BalanceBeam { child, address }
+        BalanceBeam {
+            child,
+            address,
+            config_path,
+            stderr_lines,
+        }
+    }
+
+    /// Returns the lines balancebeam has written to stderr (i.e. its log messages) so far
+    #[allow(dead_code)]
+    pub fn stderr_lines(&self) -> Vec<String> {
+        self.stderr_lines.lock().unwrap().clone()
     }
 
     #[allow(dead_code)]
@@ -96,6 +201,15 @@ impl BalanceBeam {
             .await
     }
 
+    /// Opens a plain TCP connection to balancebeam, for tests that need control over exactly what
+    /// is sent (and when). Use read_raw_response to read the replies.
+    #[allow(dead_code)]
+    pub async fn connect(&self) -> TcpStream {
+        TcpStream::connect(&self.address)
+            .await
+            .expect("Could not connect to balancebeam")
+    }
+
     #[allow(dead_code)]
     pub async fn post(&self, path: &str, body: &str) -> Result<String, reqwest::Error> {
         let client = reqwest::Client::new();
@@ -109,3 +223,61 @@ impl BalanceBeam {
             .await
     }
 }
+
+impl Drop for BalanceBeam {
+    fn drop(&mut self) {
+        if let Some(config_path) = &self.config_path {
+            let _ = std::fs::remove_file(config_path);
+        }
+    }
+}
+
+/// A response read off a raw connection (see BalanceBeam::connect)
+#[allow(dead_code)]
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: http::HeaderMap,
+    pub body: String,
+}
+
+/// Reads one response (with a Content-Length body) from the stream. Returns None if the
+/// connection is closed before a complete response arrives.
+#[allow(dead_code)]
+pub async fn read_raw_response(stream: &mut TcpStream) -> Option<RawResponse> {
+    let mut buffer = Vec::new();
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut response = httparse::Response::new(&mut headers);
+        if let httparse::Status::Complete(headers_len) = response
+            .parse(&buffer)
+            .expect("balancebeam sent a malformed response")
+        {
+            let mut header_map = http::HeaderMap::new();
+            for header in response.headers.iter() {
+                header_map.append(
+                    http::header::HeaderName::from_bytes(header.name.as_bytes()).unwrap(),
+                    http::HeaderValue::from_bytes(header.value).unwrap(),
+                );
+            }
+            let content_length: usize = header_map
+                .get("content-length")
+                .map(|value| value.to_str().unwrap().parse().unwrap())
+                .unwrap_or(0);
+            if buffer.len() >= headers_len + content_length {
+                return Some(RawResponse {
+                    status: response.code.unwrap(),
+                    headers: header_map,
+                    body: String::from_utf8_lossy(
+                        &buffer[headers_len..headers_len + content_length],
+                    )
+                    .to_string(),
+                });
+            }
+        }
+        let mut chunk = [0_u8; 4096];
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::common::server::Server;
+use async_trait::async_trait;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response};
+use rand::Rng;
+use std::sync::{atomic, Arc};
+use tokio::sync::oneshot;
+
+#[derive(Debug)]
+struct ServerState {
+    pub requests_received: atomic::AtomicUsize,
+}
+
+/// Responds to every request with a body that's different each time (it includes how many
+/// requests the server has received), so that a cached copy can be told apart from a fresh one.
+/// Responses may be cached for a minute, except under /no-store, where they can't be cached at all.
+async fn respond(
+    server_state: Arc<ServerState>,
+    req: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    let request_num = server_state
+        .requests_received
+        .fetch_add(1, atomic::Ordering::SeqCst)
+        + 1;
+    let cache_control = if req.uri().path().starts_with("/no-store") {
+        "no-store"
+    } else {
+        "max-age=60"
+    };
+    Ok(Response::builder()
+        .header("cache-control", cache_control)
+        .body(Body::from(format!(
+            "{} {} (request #{})",
+            req.method(),
+            req.uri(),
+            request_num
+        )))
+        .unwrap())
+}
+
+/// A server whose responses can (mostly) be cached
+pub struct CacheableServer {
+    shutdown_signal_sender: oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+    pub address: String,
+    state: Arc<ServerState>,
+}
+
+impl CacheableServer {
+    #[allow(dead_code)]
+    pub async fn new() -> CacheableServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let bind_addr = bind_addr_string.parse().unwrap();
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task
+        let server_state = Arc::new(ServerState {
+            requests_received: atomic::AtomicUsize::new(0),
+        });
+        let server_task_state = server_state.clone();
+        let server_task = tokio::spawn(async move {
+            let service = make_service_fn(|_| {
+                let server_task_state = server_task_state.clone();
+                async move {
+                    Ok::<_, hyper::Error>(service_fn(move |req| {
+                        respond(server_task_state.clone(), req)
+                    }))
+                }
+            });
+            let server = hyper::Server::bind(&bind_addr)
+                .serve(service)
+                .with_graceful_shutdown(async {
+                    shutdown_rx.await.ok();
+                });
+            // Start serving and wait for the server to exit
+            if let Err(e) = server.await {
+                log::error!("Error in CacheableServer: {}", e);
+            }
+        });
+
+        CacheableServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            state: server_state,
+            address: bind_addr_string,
+        }
+    }
+}
+
+#[async_trait]
+impl Server for CacheableServer {
+    async fn stop(self: Box<Self>) -> usize {
+        // Tell the hyper server to stop
+        let _ = self.shutdown_signal_sender.send(());
+        // Wait for it to stop
+        self.server_task
+            .await
+            .expect("CacheableServer server task panicked");
+
+        self.state.requests_received.load(atomic::Ordering::SeqCst)
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
@@ -0,0 +1,91 @@
+use crate::common::server::Server;
+use async_trait::async_trait;
+use rand::Rng;
+use std::sync::{atomic, Arc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// A server that answers each request without a Content-Length (or chunked encoding), so that the
+/// end of the body is marked by the server hanging up. The body is the request line that was
+/// received.
+pub struct CloseDelimitedServer {
+    shutdown_signal_sender: oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+    pub address: String,
+    requests_received: Arc<atomic::AtomicUsize>,
+}
+
+impl CloseDelimitedServer {
+    #[allow(dead_code)]
+    pub async fn new() -> CloseDelimitedServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let mut listener = TcpListener::bind(&bind_addr_string)
+            .await
+            .expect("CloseDelimitedServer could not bind");
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task
+        let requests_received = Arc::new(atomic::AtomicUsize::new(0));
+        let server_task_requests_received = requests_received.clone();
+        let server_task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    connection = listener.accept() => {
+                        if let Ok((mut stream, _)) = connection {
+                            // Read up to the end of the headers (the requests have no body)
+                            let mut request = Vec::new();
+                            let mut buffer = [0_u8; 512];
+                            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                                match stream.read(&mut buffer).await {
+                                    Ok(0) | Err(_) => break,
+                                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                                }
+                            }
+                            if request.is_empty() {
+                                continue;
+                            }
+                            server_task_requests_received.fetch_add(1, atomic::Ordering::SeqCst);
+                            let request_line = String::from_utf8_lossy(&request)
+                                .lines()
+                                .next()
+                                .unwrap_or("")
+                                .to_string();
+                            let response = format!("HTTP/1.1 200 OK\r\n\r\n{}", request_line);
+                            let _ = stream.write_all(response.as_bytes()).await;
+                            // Dropping the stream hangs up, which ends the body
+                        }
+                    }
+                    _ = &mut shutdown_rx => return,
+                }
+            }
+        });
+
+        CloseDelimitedServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            requests_received,
+            address: bind_addr_string,
+        }
+    }
+}
+
+#[async_trait]
+impl Server for CloseDelimitedServer {
+    async fn stop(self: Box<Self>) -> usize {
+        // Tell the server to stop
+        let _ = self.shutdown_signal_sender.send(());
+        // Wait for it to stop
+        self.server_task
+            .await
+            .expect("CloseDelimitedServer server task panicked");
+
+        self.requests_received.load(atomic::Ordering::SeqCst)
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
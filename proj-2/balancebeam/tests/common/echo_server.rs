@@ -3,12 +3,18 @@ use async_trait::async_trait;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response};
 use rand::Rng;
+use std::io::BufReader;
 use std::sync::{atomic, Arc};
+use tokio::net::TcpListener;
 use tokio::sync::oneshot;
+use tokio_rustls::rustls::{internal::pemfile, NoClientAuth, ServerConfig};
+use tokio_rustls::TlsAcceptor;
 
 #[derive(Debug)]
 struct ServerState {
     pub requests_received: atomic::AtomicUsize,
+    /// How many TCP connections have been opened to this server
+    pub connections_accepted: atomic::AtomicUsize,
 }
 
 async fn echo(
@@ -45,6 +51,77 @@ impl EchoServer {
         EchoServer::new_at_address(format!("127.0.0.1:{}", rng.gen_range(1024, 65535))).await
     }
 
+    /// Starts an echo server that only speaks HTTPS, presenting the certificate in tests/certs
+    /// (which is for localhost)
+    #[allow(dead_code)]
+    pub async fn new_with_tls() -> EchoServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let mut listener = TcpListener::bind(&bind_addr_string)
+            .await
+            .expect("EchoServer could not bind");
+        let mut tls_config = ServerConfig::new(NoClientAuth::new());
+        let certs = pemfile::certs(&mut BufReader::new(
+            &include_bytes!("../certs/cert.pem")[..],
+        ))
+        .unwrap();
+        let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(
+            &include_bytes!("../certs/key.pem")[..],
+        ))
+        .unwrap();
+        tls_config.set_single_cert(certs, keys.remove(0)).unwrap();
+        let tls_acceptor = TlsAcceptor::from(Arc::new(tls_config));
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task. hyper::Server can't do the TLS handshake for us, so accept
+        // the connections ourselves and hand them to hyper once they're set up
+        let server_state = Arc::new(ServerState {
+            requests_received: atomic::AtomicUsize::new(0),
+            connections_accepted: atomic::AtomicUsize::new(0),
+        });
+        let server_task_state = server_state.clone();
+        let server_task = tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    connection = listener.accept() => match connection {
+                        Ok((stream, _)) => stream,
+                        Err(_) => continue,
+                    },
+                    _ = &mut shutdown_rx => return,
+                };
+                server_task_state
+                    .connections_accepted
+                    .fetch_add(1, atomic::Ordering::SeqCst);
+                let tls_acceptor = tls_acceptor.clone();
+                let server_task_state = server_task_state.clone();
+                tokio::spawn(async move {
+                    let stream = match tls_acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            log::info!("TLS handshake failed in EchoServer: {}", e);
+                            return;
+                        }
+                    };
+                    let service = service_fn(move |req| echo(server_task_state.clone(), req));
+                    if let Err(e) = hyper::server::conn::Http::new()
+                        .serve_connection(stream, service)
+                        .await
+                    {
+                        log::error!("Error in EchoServer: {}", e);
+                    }
+                });
+            }
+        });
+
+        EchoServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            state: server_state,
+            address: bind_addr_string,
+        }
+    }
+
     pub async fn new_at_address(bind_addr_string: String) -> EchoServer {
         let bind_addr = bind_addr_string.parse().unwrap();
         // Create a one-shot channel that can be used to tell the server to shut down
@@ -53,11 +130,15 @@ impl EchoServer {
         // Start a separate server task
         let server_state = Arc::new(ServerState {
             requests_received: atomic::AtomicUsize::new(0),
+            connections_accepted: atomic::AtomicUsize::new(0),
         });
         let server_task_state = server_state.clone();
         let server_task = tokio::spawn(async move {
             let service = make_service_fn(|_| {
                 let server_task_state = server_task_state.clone();
+                server_task_state
+                    .connections_accepted
+                    .fetch_add(1, atomic::Ordering::SeqCst);
                 async move {
                     Ok::<_, hyper::Error>(service_fn(move |req| {
                         let server_task_state = server_task_state.clone();
@@ -83,6 +164,14 @@ impl EchoServer {
             address: bind_addr_string,
         }
     }
+
+    /// Returns how many TCP connections have been opened to this server so far
+    #[allow(dead_code)]
+    pub fn connections_accepted(&self) -> usize {
+        self.state
+            .connections_accepted
+            .load(atomic::Ordering::SeqCst)
+    }
 }
 
 #[async_trait]
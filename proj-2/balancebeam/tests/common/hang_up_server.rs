@@ -0,0 +1,78 @@
+use crate::common::server::Server;
+use async_trait::async_trait;
+use rand::Rng;
+use std::sync::{atomic, Arc};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// A server that accepts connections, but hangs up on each one as soon as a request starts to
+/// arrive, without ever responding. Unlike a server that isn't running at all, balancebeam can't
+/// tell anything is wrong until it has already sent a request.
+pub struct HangUpServer {
+    shutdown_signal_sender: oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+    pub address: String,
+    requests_received: Arc<atomic::AtomicUsize>,
+}
+
+impl HangUpServer {
+    #[allow(dead_code)]
+    pub async fn new() -> HangUpServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let mut listener = TcpListener::bind(&bind_addr_string)
+            .await
+            .expect("HangUpServer could not bind");
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task
+        let requests_received = Arc::new(atomic::AtomicUsize::new(0));
+        let server_task_requests_received = requests_received.clone();
+        let server_task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    connection = listener.accept() => {
+                        if let Ok((mut stream, _)) = connection {
+                            let mut buffer = [0_u8; 512];
+                            if let Ok(bytes_read) = stream.read(&mut buffer).await {
+                                if bytes_read > 0 {
+                                    server_task_requests_received
+                                        .fetch_add(1, atomic::Ordering::SeqCst);
+                                }
+                            }
+                            // Dropping the stream hangs up on the client
+                        }
+                    }
+                    _ = &mut shutdown_rx => return,
+                }
+            }
+        });
+
+        HangUpServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            requests_received,
+            address: bind_addr_string,
+        }
+    }
+}
+
+#[async_trait]
+impl Server for HangUpServer {
+    async fn stop(self: Box<Self>) -> usize {
+        // Tell the server to stop
+        let _ = self.shutdown_signal_sender.send(());
+        // Wait for it to stop
+        self.server_task
+            .await
+            .expect("HangUpServer server task panicked");
+
+        self.requests_received.load(atomic::Ordering::SeqCst)
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
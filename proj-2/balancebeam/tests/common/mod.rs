@@ -1,14 +1,37 @@
 mod balancebeam;
+mod cacheable_server;
+mod chunked_echo_server;
+mod close_delimited_server;
 mod echo_server;
 mod error_server;
+mod hang_up_server;
+mod healthz_server;
 mod server;
+mod slow_server;
+mod upgrade_echo_server;
 
 use std::sync;
 
-pub use balancebeam::BalanceBeam;
+// Not every test file uses every helper
+#[allow(unused_imports)]
+pub use balancebeam::{read_raw_response, BalanceBeam};
+#[allow(unused_imports)]
+pub use cacheable_server::CacheableServer;
+#[allow(unused_imports)]
+pub use chunked_echo_server::ChunkedEchoServer;
+#[allow(unused_imports)]
+pub use close_delimited_server::CloseDelimitedServer;
 pub use echo_server::EchoServer;
 pub use error_server::ErrorServer;
+#[allow(unused_imports)]
+pub use hang_up_server::HangUpServer;
+#[allow(unused_imports)]
+pub use healthz_server::HealthzServer;
 pub use server::Server;
+#[allow(unused_imports)]
+pub use slow_server::SlowServer;
+#[allow(unused_imports)]
+pub use upgrade_echo_server::UpgradeEchoServer;
 
 static INIT_TESTS: sync::Once = sync::Once::new();
 
@@ -0,0 +1,111 @@
+use crate::common::server::Server;
+use async_trait::async_trait;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response};
+use rand::Rng;
+use std::sync::{atomic, Arc};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::delay_for;
+
+#[derive(Debug)]
+struct ServerState {
+    pub requests_received: atomic::AtomicUsize,
+    /// How long to wait before answering each request
+    pub delay: Duration,
+}
+
+/// Waits for the server's delay, then echoes the request back like EchoServer does
+async fn respond(
+    server_state: Arc<ServerState>,
+    req: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    server_state
+        .requests_received
+        .fetch_add(1, atomic::Ordering::SeqCst);
+    delay_for(server_state.delay).await;
+    let mut req_text = format!("{} {} {:?}\n", req.method(), req.uri(), req.version());
+    for (header_name, header_value) in req.headers() {
+        req_text += &format!(
+            "{}: {}\n",
+            header_name.as_str(),
+            header_value.to_str().unwrap_or("<binary value>")
+        );
+    }
+    req_text += "\n";
+    let mut req_as_bytes = req_text.into_bytes();
+    req_as_bytes.extend(hyper::body::to_bytes(req.into_body()).await?);
+    Ok(Response::new(Body::from(req_as_bytes)))
+}
+
+/// An echo server that takes a while to answer each request, to simulate a slow (or overloaded)
+/// upstream. Requests are counted as soon as they arrive, whether or not they're ever answered.
+pub struct SlowServer {
+    shutdown_signal_sender: oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+    pub address: String,
+    state: Arc<ServerState>,
+}
+
+impl SlowServer {
+    /// Starts a server that waits `delay` before answering each request
+    #[allow(dead_code)]
+    pub async fn new(delay: Duration) -> SlowServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let bind_addr = bind_addr_string.parse().unwrap();
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task
+        let server_state = Arc::new(ServerState {
+            requests_received: atomic::AtomicUsize::new(0),
+            delay,
+        });
+        let server_task_state = server_state.clone();
+        let server_task = tokio::spawn(async move {
+            let service = make_service_fn(|_| {
+                let server_task_state = server_task_state.clone();
+                async move {
+                    Ok::<_, hyper::Error>(service_fn(move |req| {
+                        respond(server_task_state.clone(), req)
+                    }))
+                }
+            });
+            let server = hyper::Server::bind(&bind_addr)
+                .serve(service)
+                .with_graceful_shutdown(async {
+                    shutdown_rx.await.ok();
+                });
+            // Start serving and wait for the server to exit
+            if let Err(e) = server.await {
+                log::error!("Error in SlowServer: {}", e);
+            }
+        });
+
+        SlowServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            state: server_state,
+            address: bind_addr_string,
+        }
+    }
+}
+
+#[async_trait]
+impl Server for SlowServer {
+    async fn stop(self: Box<Self>) -> usize {
+        // Tell the hyper server to stop
+        let _ = self.shutdown_signal_sender.send(());
+        // Wait for it to stop
+        self.server_task
+            .await
+            .expect("SlowServer server task panicked");
+
+        self.state.requests_received.load(atomic::Ordering::SeqCst)
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
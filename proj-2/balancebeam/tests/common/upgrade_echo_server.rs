@@ -0,0 +1,110 @@
+use crate::common::server::Server;
+use async_trait::async_trait;
+use rand::Rng;
+use std::sync::{atomic, Arc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+/// Reads a request's headers, then switches protocols if it asked for a WebSocket. Returns whether
+/// the connection was upgraded.
+async fn upgrade(stream: &mut TcpStream) -> bool {
+    let mut request = Vec::new();
+    let mut buffer = [0_u8; 512];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return false,
+            Ok(bytes_read) => request.extend_from_slice(&buffer[..bytes_read]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+    if !(request.contains("\r\nconnection: upgrade\r\n")
+        && request.contains("\r\nupgrade: websocket\r\n"))
+    {
+        let _ = stream
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+            .await;
+        return false;
+    }
+    // A real WebSocket server would work out Sec-WebSocket-Accept from the client's key, but the
+    // tests don't check it
+    stream
+        .write_all(
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+            Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+        )
+        .await
+        .is_ok()
+}
+
+/// A server that accepts WebSocket upgrades, then echoes back whatever bytes it receives over the
+/// upgraded connection (without looking at the WebSocket framing). It counts the connections it
+/// upgrades.
+pub struct UpgradeEchoServer {
+    shutdown_signal_sender: oneshot::Sender<()>,
+    server_task: tokio::task::JoinHandle<()>,
+    pub address: String,
+    upgrades: Arc<atomic::AtomicUsize>,
+}
+
+impl UpgradeEchoServer {
+    #[allow(dead_code)]
+    pub async fn new() -> UpgradeEchoServer {
+        let mut rng = rand::thread_rng();
+        let bind_addr_string = format!("127.0.0.1:{}", rng.gen_range(1024, 65535));
+        let mut listener = TcpListener::bind(&bind_addr_string)
+            .await
+            .expect("UpgradeEchoServer could not bind");
+        // Create a one-shot channel that can be used to tell the server to shut down
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        // Start a separate server task
+        let upgrades = Arc::new(atomic::AtomicUsize::new(0));
+        let server_task_upgrades = upgrades.clone();
+        let server_task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    connection = listener.accept() => {
+                        if let Ok((mut stream, _)) = connection {
+                            let upgrades = server_task_upgrades.clone();
+                            tokio::spawn(async move {
+                                if !upgrade(&mut stream).await {
+                                    return;
+                                }
+                                upgrades.fetch_add(1, atomic::Ordering::SeqCst);
+                                let (mut reader, mut writer) = stream.split();
+                                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                            });
+                        }
+                    }
+                    _ = &mut shutdown_rx => return,
+                }
+            }
+        });
+
+        UpgradeEchoServer {
+            shutdown_signal_sender: shutdown_tx,
+            server_task,
+            upgrades,
+            address: bind_addr_string,
+        }
+    }
+}
+
+#[async_trait]
+impl Server for UpgradeEchoServer {
+    async fn stop(self: Box<Self>) -> usize {
+        // Tell the server to stop
+        let _ = self.shutdown_signal_sender.send(());
+        // Wait for it to stop
+        self.server_task
+            .await
+            .expect("UpgradeEchoServer server task panicked");
+
+        self.upgrades.load(atomic::Ordering::SeqCst)
+    }
+
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+}
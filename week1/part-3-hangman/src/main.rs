@@ -1,6 +1,7 @@
 // Simple Hangman Program
-// User gets five incorrect guesses
-// Word chosen randomly from words.txt
+// User gets five incorrect guesses (or however many are given with --guesses)
+// Word chosen randomly from words.txt, or from words/<category>.txt if a category is given
+// (or, with --custom, typed in by another player)
 // Inspiration from: https://doc.rust-lang.org/book/ch02-00-guessing-game-tutorial.html
 // This assignment will introduce you to some fundamental syntax in Rust:
 // - variable declaration
@@ -14,27 +15,129 @@
 // more in depth in the coming lectures.
 extern crate rand;
 use rand::Rng;
+use std::env;
 use std::fs;
 use std::io;
 use std::io::Write;
+use std::process;
 
 const NUM_INCORRECT_GUESSES: u32 = 5;
 const WORDS_PATH: &str = "words.txt";
 
-fn pick_a_random_word() -> String {
-    let file_string = fs::read_to_string(WORDS_PATH).expect("Unable to read file.");
-    let words: Vec<&str> = file_string.split('\n').collect();
-    String::from(words[rand::thread_rng().gen_range(0, words.len())].trim())
+fn pick_a_random_word(path: &str) -> Result<String, String> {
+    let file_string = match fs::read_to_string(path) {
+        Ok(file_string) => file_string,
+        Err(err) => return Err(format!("Unable to read words from {}: {}", path, err)),
+    };
+    // Skip blank lines (like the one after a trailing newline), which would make for a game with
+    // nothing to guess
+    let words: Vec<&str> = file_string
+        .split('\n')
+        .map(|word| word.trim())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return Err(format!("There aren't any words in {}", path));
+    }
+    Ok(String::from(
+        words[rand::thread_rng().gen_range(0, words.len())],
+    ))
+}
+
+// Reads how many incorrect guesses the user gets from a "--guesses N" argument, if there is one
+fn parse_num_incorrect_guesses(args: &[String]) -> Result<u32, String> {
+    let flag_index = match args.iter().position(|arg| arg == "--guesses") {
+        Some(index) => index,
+        None => return Ok(NUM_INCORRECT_GUESSES),
+    };
+    match args.get(flag_index + 1) {
+        Some(num) => match num.parse::<u32>() {
+            Ok(num) if num > 0 => Ok(num),
+            _ => Err(format!("--guesses needs a number above 0, not {:?}", num)),
+        },
+        None => Err(String::from("--guesses must be followed by a number")),
+    }
+}
+
+// Works out which file to pick the word from: words/<category>.txt if a category (like "animals")
+// is given on the command line, or WORDS_PATH if not
+fn words_path(args: &[String]) -> String {
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--guesses" {
+            // Skip over the number that goes with it
+            rest.next();
+            continue;
+        }
+        if arg == "--custom" {
+            continue;
+        }
+        return format!("words/{}.txt", arg);
+    }
+    String::from(WORDS_PATH)
+}
+
+// Asks the first player for the secret word, hiding what they type (if the terminal lets us) so
+// that the second player can't see it
+fn read_secret_word() -> Result<String, String> {
+    print!("Player 1, enter the secret word: ");
+    io::stdout().flush().expect("Error flushing stdout.");
+    let hidden = set_echo(false);
+    let mut word = String::new();
+    let result = io::stdin().read_line(&mut word);
+    if hidden {
+        set_echo(true);
+        // The Enter key wasn't echoed either
+        println!();
+    }
+    match result {
+        Ok(_) if !word.trim().is_empty() => Ok(String::from(word.trim())),
+        Ok(_) => Err(String::from("The secret word can't be empty")),
+        Err(err) => Err(format!("Unable to read the secret word: {}", err)),
+    }
+}
+
+// Turns the terminal's echoing of what's typed on or off, returning whether that worked. (It
+// doesn't if we aren't reading from a terminal, in which case there's nothing to hide anyway.)
+fn set_echo(on: bool) -> bool {
+    process::Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stderr(process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
 }
 
 fn main() {
-    let secret_word = pick_a_random_word();
+    let args: Vec<String> = env::args().collect();
+    // The number of incorrect guesses the user has left, which the game loop counts down
+    let left_chance = match parse_num_incorrect_guesses(&args) {
+        Ok(num) => num,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
+
+    let secret_word = if args.iter().any(|arg| arg == "--custom") {
+        read_secret_word()
+    } else {
+        pick_a_random_word(&words_path(&args))
+    };
+    let secret_word = match secret_word {
+        Ok(secret_word) => secret_word,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    };
     // Note: given what you know about Rust so far, it's easier to pull characters out of a
     // vector than it is to pull them out of a string. You can get the ith character of
     // secret_word by doing secret_word_chars[i].
     let secret_word_chars: Vec<char> = secret_word.chars().collect();
     // Uncomment for debugging:
     // println!("random word: {}", secret_word);
+    println!("You can make {} incorrect guesses.", left_chance);
 
     // Your code here! :)
 }
@@ -0,0 +1,4 @@
+pub mod open_file;
+pub mod pipes;
+pub mod process;
+pub mod ps_utils;
@@ -1,20 +1,88 @@
+use inspect_fds::process::Process;
+use inspect_fds::{pipes, ps_utils};
 use std::env;
 
-mod open_file;
-mod process;
-mod ps_utils;
-
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <name or pid of target>", args[0]);
+    let (flags, targets): (Vec<&String>, Vec<&String>) =
+        args[1..].iter().partition(|arg| arg.starts_with("--"));
+    let mut reverse = false;
+    let mut all = false;
+    let mut show_parent = false;
+    for flag in flags {
+        match flag.as_str() {
+            "--reverse" => reverse = true,
+            "--all" => all = true,
+            "--show-parent" => show_parent = true,
+            _ => {
+                println!("Unrecognized option: {}", flag);
+                print_usage(&args[0]);
+            }
+        }
+    }
+
+    if all {
+        print_all_processes();
+        return;
+    }
+    if targets.is_empty() {
+        print_usage(&args[0]);
+    }
+
+    // Resolve each target along with its children. A target that doesn't match anything is only
+    // a warning, so that the remaining targets still get printed. Each process is paired with a
+    // flag indicating whether it was one of the targets (as opposed to a child of one).
+    let mut processes = Vec::new();
+    for target in targets {
+        match ps_utils::get_target(target).expect("Error calling get_target") {
+            Some(process) => {
+                let children = ps_utils::get_child_processes(process.pid)
+                    .expect("Error calling get_child_processes");
+                processes.push((process, true));
+                processes.extend(children.into_iter().map(|child| (child, false)));
+            }
+            None => println!(
+                "Warning: target \"{}\" did not match any running PIDs or executables",
+                target
+            ),
+        }
+    }
+    if processes.is_empty() {
         std::process::exit(1);
     }
-    #[allow(unused)] // TODO: delete this line for Milestone 1
-    let target = &args[1];
 
-    // TODO: Milestone 1: Get the target Process using psutils::get_target()
-    unimplemented!();
+    for (process, is_target) in &processes {
+        if show_parent && *is_target {
+            print_parent(process);
+        }
+        process.print(reverse);
+    }
+    let processes: Vec<Process> = processes.into_iter().map(|(process, _)| process).collect();
+    pipes::print_pipe_connections(&processes);
+}
+
+fn print_usage(program: &str) -> ! {
+    println!(
+        "Usage: {} [--reverse] [--show-parent] <name or pid of target>... | --all",
+        program
+    );
+    std::process::exit(1);
+}
+
+/// Prints the command of the specified process's parent, if it has one.
+fn print_parent(process: &Process) {
+    match ps_utils::get_parent_process(process).expect("Error calling get_parent_process") {
+        Some(parent) => println!("Parent: \"{}\" (pid {})", parent.command, parent.pid),
+        None => println!("Parent: <none>"),
+    }
+}
+
+/// Prints a one-line summary for every process owned by the current user. Processes that exit
+/// before we get a chance to inspect their fd tables are silently skipped.
+fn print_all_processes() {
+    for process in ps_utils::get_user_processes().expect("Error calling get_user_processes") {
+        process.print_summary();
+    }
 }
 
 #[cfg(test)]
@@ -43,6 +111,37 @@ mod test {
         let _ = subprocess.kill();
     }
 
+    #[test]
+    fn test_exit_status_all() {
+        assert_eq!(
+            Command::new("./target/debug/inspect-fds")
+                .args(["--all"])
+                .status()
+                .expect("Could not find target/debug/inspect-fds. Is the binary compiled?")
+                .code()
+                .expect("Program was unexpectedly terminated by a signal"),
+            0,
+            "We expected the program to exit normally, but it didn't."
+        );
+    }
+
+    #[test]
+    fn test_exit_status_partially_valid_targets() {
+        let mut subprocess = start_c_program("./multi_pipe_test");
+        assert_eq!(
+            Command::new("./target/debug/inspect-fds")
+                .args(["./nonexistent", &subprocess.id().to_string()])
+                .status()
+                .expect("Could not find target/debug/inspect-fds. Is the binary compiled?")
+                .code()
+                .expect("Program was unexpectedly terminated by a signal"),
+            0,
+            "We expected the program to exit normally when at least one target is valid, but it \
+            didn't."
+        );
+        let _ = subprocess.kill();
+    }
+
     #[test]
     fn test_exit_status_invalid_target() {
         assert_eq!(
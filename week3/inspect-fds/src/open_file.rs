@@ -1,14 +1,9 @@
 use regex::Regex;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-#[allow(unused_imports)] // TODO: delete this line for Milestone 4
 use std::{fmt, fs};
-
-#[allow(unused)] // TODO: delete this line for Milestone 4
 const O_WRONLY: usize = 00000001;
-#[allow(unused)] // TODO: delete this line for Milestone 4
 const O_RDWR: usize = 00000002;
-#[allow(unused)] // TODO: delete this line for Milestone 4
 const COLORS: [&str; 6] = [
     "\x1B[38;5;9m",
     "\x1B[38;5;10m",
@@ -17,12 +12,10 @@ const COLORS: [&str; 6] = [
     "\x1B[38;5;13m",
     "\x1B[38;5;14m",
 ];
-#[allow(unused)] // TODO: delete this line for Milestone 4
 const CLEAR_COLOR: &str = "\x1B[0m";
 
 /// This enum can be used to represent whether a file is read-only, write-only, or read/write. An
 /// enum is basically a value that can be one of some number of "things."
-#[allow(unused)] // TODO: delete this line for Milestone 4
 #[derive(Debug, Clone, PartialEq)]
 pub enum AccessMode {
     Read,
@@ -53,7 +46,6 @@ pub struct OpenFile {
 }
 
 impl OpenFile {
-    #[allow(unused)] // TODO: delete this line for Milestone 4
     pub fn new(name: String, cursor: usize, access_mode: AccessMode) -> OpenFile {
         OpenFile {
             name,
@@ -66,12 +58,12 @@ impl OpenFile {
     /// file.
     ///
     /// * For regular files, this will simply return the supplied path.
-    /// * For terminals (files starting with /dev/pts), this will return "<terminal>".
+    /// * For terminals (files starting with /dev/pts), this will return "<terminal /dev/pts/N>",
+    ///   so that fds pointing to different terminals can be told apart.
     /// * For pipes (filenames formatted like pipe:[pipenum]), this will return "<pipe #pipenum>".
-    #[allow(unused)] // TODO: delete this line for Milestone 4
     fn path_to_name(path: &str) -> String {
         if path.starts_with("/dev/pts/") {
-            String::from("<terminal>")
+            format!("<terminal {}>", path)
         } else if path.starts_with("pipe:[") && path.ends_with("]") {
             let pipe_num = &path[path.find('[').unwrap() + 1..path.find(']').unwrap()];
             format!("<pipe #{}>", pipe_num)
@@ -84,7 +76,6 @@ impl OpenFile {
     /// extracts the cursor position of that file descriptor (technically, the position of the
     /// open file table entry that the fd points to) using a regex. It returns None if the cursor
     /// couldn't be found in the fdinfo text.
-    #[allow(unused)] // TODO: delete this line for Milestone 4
     fn parse_cursor(fdinfo: &str) -> Option<usize> {
         // Regex::new will return an Error if there is a syntactical error in our regular
         // expression. We call unwrap() here because that indicates there's an obvious problem with
@@ -103,7 +94,6 @@ impl OpenFile {
     /// This file takes the contents of /proc/{pid}/fdinfo/{fdnum} for some file descriptor and
     /// extracts the access mode for that open file using the "flags:" field contained in the
     /// fdinfo text. It returns None if the "flags" field couldn't be found.
-    #[allow(unused)] // TODO: delete this line for Milestone 4
     fn parse_access_mode(fdinfo: &str) -> Option<AccessMode> {
         // Regex::new will return an Error if there is a syntactical error in our regular
         // expression. We call unwrap() here because that indicates there's an obvious problem with
@@ -134,17 +124,30 @@ impl OpenFile {
     /// program and we don't need to do fine-grained error handling, so returning Option is a
     /// simple way to indicate that "hey, we weren't able to get the necessary information"
     /// without making a big deal of it.)
-    #[allow(unused)] // TODO: delete this line for Milestone 4
     pub fn from_fd(pid: usize, fd: usize) -> Option<OpenFile> {
-        // TODO: implement for Milestone 4
-        unimplemented!();
+        let path = fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok()?;
+        let fdinfo = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd)).ok()?;
+        Some(OpenFile::new(
+            OpenFile::path_to_name(path.to_str()?),
+            OpenFile::parse_cursor(&fdinfo)?,
+            OpenFile::parse_access_mode(&fdinfo)?,
+        ))
+    }
+
+    /// This function returns the pipe number if this OpenFile refers to a pipe (i.e. its name was
+    /// generated by path_to_name as "<pipe #pipenum>"), or None otherwise.
+    pub fn pipe_number(&self) -> Option<usize> {
+        self.name
+            .strip_prefix("<pipe #")?
+            .strip_suffix('>')?
+            .parse::<usize>()
+            .ok()
     }
 
     /// This function returns the OpenFile's name with ANSI escape codes included to colorize
     /// pipe names. It hashes the pipe name so that the same pipe name will always result in the
     /// same color. This is useful for making program output more readable, since a user can
     /// quickly see all the fds that point to a particular pipe.
-    #[allow(unused)] // TODO: delete this line for Milestone 5
     pub fn colorized_name(&self) -> String {
         if self.name.starts_with("<pipe") {
             let mut hash = DefaultHasher::new();
@@ -177,12 +180,28 @@ mod test {
         // Get file descriptor 0, which should point to the terminal
         let open_file = OpenFile::from_fd(process.pid, 0)
             .expect("Expected to get open file data for multi_pipe_test, but OpenFile::from_fd returned None");
-        assert_eq!(open_file.name, "<terminal>");
+        assert!(
+            open_file.name.starts_with("<terminal /dev/pts/"),
+            "Expected fd 0 to point to a terminal, but got {}",
+            open_file.name
+        );
         assert_eq!(open_file.cursor, 0);
         assert_eq!(open_file.access_mode, AccessMode::ReadWrite);
         let _ = test_subprocess.kill();
     }
 
+    #[test]
+    fn test_path_to_name_terminal() {
+        assert_eq!(
+            OpenFile::path_to_name("/dev/pts/0"),
+            "<terminal /dev/pts/0>"
+        );
+        assert_eq!(
+            OpenFile::path_to_name("/dev/pts/12"),
+            "<terminal /dev/pts/12>"
+        );
+    }
+
     #[test]
     fn test_openfile_from_fd_invalid_fd() {
         let mut test_subprocess = start_c_program("./multi_pipe_test");
@@ -0,0 +1,207 @@
+use crate::open_file::AccessMode;
+use crate::process::Process;
+use std::collections::BTreeMap;
+
+/// Represents one end of a pipe: a file descriptor in some process that points to an open file
+/// table entry for the pipe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipeEndpoint {
+    pub pid: usize,
+    pub fd: usize,
+    pub access_mode: AccessMode,
+}
+
+/// This function takes a list of processes and groups all of their pipe file descriptors by pipe
+/// number, so that a caller can see which processes are connected by each pipe. Processes whose
+/// fd tables can't be inspected (e.g. because they have exited) are skipped.
+pub fn group_pipe_endpoints(processes: &[Process]) -> BTreeMap<usize, Vec<PipeEndpoint>> {
+    let mut pipes: BTreeMap<usize, Vec<PipeEndpoint>> = BTreeMap::new();
+    for process in processes {
+        let open_files = match process.list_open_files() {
+            Some(open_files) => open_files,
+            None => continue,
+        };
+        for (fd, file) in open_files {
+            if let Some(pipe_num) = file.pipe_number() {
+                pipes.entry(pipe_num).or_default().push(PipeEndpoint {
+                    pid: process.pid,
+                    fd,
+                    access_mode: file.access_mode,
+                });
+            }
+        }
+    }
+    pipes
+}
+
+/// This function prints one line for every (write end, read end) pair of each pipe shared by the
+/// given processes, e.g. "pipe #123 connects PID 500 fd 4 -> PID 600 fd 3".
+pub fn print_pipe_connections(processes: &[Process]) {
+    for (pipe_num, endpoints) in group_pipe_endpoints(processes) {
+        for writer in endpoints
+            .iter()
+            .filter(|e| e.access_mode != AccessMode::Read)
+        {
+            for reader in endpoints
+                .iter()
+                .filter(|e| e.access_mode != AccessMode::Write)
+            {
+                if writer == reader {
+                    continue;
+                }
+                println!(
+                    "pipe #{} connects PID {} fd {} -> PID {} fd {}",
+                    pipe_num, writer.pid, writer.fd, reader.pid, reader.fd
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ps_utils;
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+    use std::time::Duration;
+
+    fn start_c_program(program: &str) -> Child {
+        Command::new(program)
+            .spawn()
+            .expect(&format!("Could not find {}. Have you run make?", program))
+    }
+
+    /// Calls f every 10ms until it returns Some, panicking with the given message if it still
+    /// hasn't after a second (multi_pipe_test's child only sticks around for two)
+    fn wait_for<T>(message: &str, mut f: impl FnMut() -> Option<T>) -> T {
+        for _ in 0..100 {
+            if let Some(value) = f() {
+                return value;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("{}", message);
+    }
+
+    /// Returns the pipes with both a read end and a write end among the given processes. (stdout
+    /// and stderr may also be pipes, depending on how the tests are run, but only the write ends
+    /// of those are in the processes we started.)
+    fn connected_pipes(processes: &[Process]) -> Vec<Vec<PipeEndpoint>> {
+        group_pipe_endpoints(processes)
+            .into_values()
+            .filter(|endpoints| {
+                endpoints.iter().any(|e| e.access_mode == AccessMode::Read)
+                    && endpoints.iter().any(|e| e.access_mode == AccessMode::Write)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_group_pipe_endpoints() {
+        let mut test_subprocess = start_c_program("./multi_pipe_test");
+        let parent = ps_utils::get_target(&test_subprocess.id().to_string())
+            .unwrap()
+            .unwrap();
+        let mut processes = wait_for("Expected multi_pipe_test to fork a child", || {
+            let children = ps_utils::get_child_processes(parent.pid).unwrap();
+            if children.is_empty() {
+                None
+            } else {
+                Some(children)
+            }
+        });
+        assert_eq!(
+            processes.len(),
+            1,
+            "Expected multi_pipe_test to have one child"
+        );
+        let child_pid = processes[0].pid;
+        processes.push(parent.clone());
+
+        // Both processes close the pipe ends they don't use right after forking, so until they
+        // have, a pipe can have more than one end in each direction
+        let shared_pipes = wait_for(
+            "Expected multi_pipe_test to share two pipes with its child",
+            || {
+                let pipes = connected_pipes(&processes);
+                if pipes.len() == 2 && pipes.iter().all(|endpoints| endpoints.len() == 2) {
+                    Some(pipes)
+                } else {
+                    None
+                }
+            },
+        );
+        for endpoints in &shared_pipes {
+            let writer = endpoints
+                .iter()
+                .find(|e| e.access_mode == AccessMode::Write)
+                .expect("Expected each pipe to have a write end");
+            let reader = endpoints
+                .iter()
+                .find(|e| e.access_mode == AccessMode::Read)
+                .expect("Expected each pipe to have a read end");
+            // The parent writes to the first pipe (fd 4), which the child reads from stdin; the
+            // child writes to the second pipe from stdout, which the parent reads (fd 5).
+            if writer.pid == parent.pid {
+                assert_eq!((writer.fd, reader.pid, reader.fd), (4, child_pid, 0));
+            } else {
+                assert_eq!((writer.pid, writer.fd), (child_pid, 1));
+                assert_eq!((reader.pid, reader.fd), (parent.pid, 5));
+            }
+        }
+        let _ = test_subprocess.kill();
+        let _ = test_subprocess.wait();
+    }
+
+    #[test]
+    fn test_group_pipe_endpoints_sibling() {
+        // Connect multi_pipe_test's stdout to the stdin of a separately started process, like a
+        // shell pipeline does, so the two are siblings rather than parent and child
+        let mut test_subprocess = Command::new("./multi_pipe_test")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Could not find ./multi_pipe_test. Have you run make?");
+        let mut sibling = Command::new("cat")
+            .stdin(test_subprocess.stdout.take().unwrap())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Could not run cat");
+        let processes = vec![
+            ps_utils::get_process(test_subprocess.id() as usize)
+                .unwrap()
+                .unwrap(),
+            ps_utils::get_process(sibling.id() as usize)
+                .unwrap()
+                .unwrap(),
+        ];
+
+        let pipeline = connected_pipes(&processes)
+            .into_iter()
+            .find(|endpoints| {
+                endpoints
+                    .iter()
+                    .any(|e| e.pid == sibling.id() as usize && e.fd == 0)
+            })
+            .expect("Expected cat's stdin to be a pipe shared with multi_pipe_test");
+        assert_eq!(
+            pipeline,
+            vec![
+                PipeEndpoint {
+                    pid: test_subprocess.id() as usize,
+                    fd: 1,
+                    access_mode: AccessMode::Write,
+                },
+                PipeEndpoint {
+                    pid: sibling.id() as usize,
+                    fd: 0,
+                    access_mode: AccessMode::Read,
+                },
+            ]
+        );
+        let _ = test_subprocess.kill();
+        let _ = sibling.kill();
+        let _ = test_subprocess.wait();
+        let _ = sibling.wait();
+    }
+}
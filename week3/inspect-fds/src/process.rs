@@ -1,5 +1,4 @@
-use crate::open_file::OpenFile;
-#[allow(unused)] // TODO: delete this line for Milestone 3
+use crate::open_file::{AccessMode, OpenFile};
 use std::fs;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -10,7 +9,6 @@ pub struct Process {
 }
 
 impl Process {
-    #[allow(unused)] // TODO: delete this line for Milestone 1
     pub fn new(pid: usize, ppid: usize, command: String) -> Process {
         Process { pid, ppid, command }
     }
@@ -19,17 +17,22 @@ impl Process {
     /// information is available (it will return None if the information is unavailable). The
     /// information will commonly be unavailable if the process has exited. (Zombie processes
     /// still have a pid, but their resources have already been freed, including the file
-    /// descriptor table.)
-    #[allow(unused)] // TODO: delete this line for Milestone 3
+    /// descriptor table.) The fds are returned in ascending order, since the order of entries in
+    /// /proc/{pid}/fd isn't guaranteed.
     pub fn list_fds(&self) -> Option<Vec<usize>> {
-        // TODO: implement for Milestone 3
-        unimplemented!();
+        let mut fds = Vec::new();
+        for entry in fs::read_dir(format!("/proc/{}/fd", self.pid)).ok()? {
+            let entry = entry.ok()?;
+            fds.push(entry.file_name().to_str()?.parse::<usize>().ok()?);
+        }
+        fds.sort_unstable();
+        Some(fds)
     }
 
     /// This function returns a list of (fdnumber, OpenFile) tuples, if file descriptor
     /// information is available (it returns None otherwise). The information is commonly
-    /// unavailable if the process has already exited.
-    #[allow(unused)] // TODO: delete this line for Milestone 4
+    /// unavailable if the process has already exited. Like list_fds, the tuples are sorted by fd
+    /// number.
     pub fn list_open_files(&self) -> Option<Vec<(usize, OpenFile)>> {
         let mut open_files = vec![];
         for fd in self.list_fds()? {
@@ -37,10 +40,78 @@ impl Process {
         }
         Some(open_files)
     }
+
+    /// This function prints a header line for this Process, followed by one line for each of its
+    /// open files. The open files are printed in ascending fd order, or in descending order if
+    /// `reverse` is true.
+    pub fn print(&self, reverse: bool) {
+        println!(
+            "========== \"{}\" (pid {}, ppid {}) ==========",
+            self.command, self.pid, self.ppid
+        );
+        match self.list_open_files() {
+            None => println!(
+                "Warning: could not inspect file descriptors for this process! It might have \
+                exited just as we were about to look at its fd table, or it might have exited a \
+                while ago and is waiting for the parent to reap it."
+            ),
+            Some(mut open_files) => {
+                if reverse {
+                    open_files.reverse();
+                }
+                for (fd, file) in &open_files {
+                    println!(
+                        "{:<4} {:<15} cursor: {:<4} {}",
+                        fd,
+                        format!("({})", file.access_mode),
+                        file.cursor,
+                        file.colorized_name(),
+                    );
+                }
+                println!("{}", summarize_open_files(&open_files));
+            }
+        }
+    }
+
+    /// This function prints a one-line summary of this Process containing its pid, its number of
+    /// open file descriptors, and its command. It returns false (and prints nothing) if the fd
+    /// table could not be inspected, e.g. because the process exited while we were looking at it.
+    pub fn print_summary(&self) -> bool {
+        match self.list_fds() {
+            Some(fds) => {
+                println!("{:<7} {:>4} fds  {}", self.pid, fds.len(), self.command);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// This function takes a list of (fdnumber, OpenFile) tuples (as returned by list_open_files) and
+/// returns a line tallying the file descriptors by access mode, e.g.
+/// "5 open file descriptors (2 read, 1 write, 2 read/write)".
+pub fn summarize_open_files(open_files: &[(usize, OpenFile)]) -> String {
+    let (mut read, mut write, mut read_write) = (0, 0, 0);
+    for (_, file) in open_files {
+        match file.access_mode {
+            AccessMode::Read => read += 1,
+            AccessMode::Write => write += 1,
+            AccessMode::ReadWrite => read_write += 1,
+        }
+    }
+    format!(
+        "{} open file descriptor{} ({} read, {} write, {} read/write)",
+        open_files.len(),
+        if open_files.len() == 1 { "" } else { "s" },
+        read,
+        write,
+        read_write
+    )
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
     use crate::ps_utils;
     use std::process::{Child, Command};
 
@@ -63,6 +134,22 @@ mod test {
         let _ = test_subprocess.kill();
     }
 
+    #[test]
+    fn test_list_open_files_sorted() {
+        let mut test_subprocess = start_c_program("./multi_pipe_test");
+        let process = ps_utils::get_target(&test_subprocess.id().to_string())
+            .unwrap()
+            .unwrap();
+        let fds: Vec<usize> = process
+            .list_open_files()
+            .expect("Expected list_open_files to find open files, but it returned None")
+            .into_iter()
+            .map(|(fd, _)| fd)
+            .collect();
+        assert_eq!(fds, vec![0, 1, 2, 4, 5]);
+        let _ = test_subprocess.kill();
+    }
+
     #[test]
     fn test_list_fds_zombie() {
         let mut test_subprocess = start_c_program("./nothing");
@@ -73,4 +160,24 @@ mod test {
         );
         let _ = test_subprocess.kill();
     }
+
+    #[test]
+    fn test_summarize_open_files() {
+        let file = |access_mode| OpenFile::new(String::from("/dev/null"), 0, access_mode);
+        let open_files = vec![
+            (0, file(AccessMode::Read)),
+            (1, file(AccessMode::ReadWrite)),
+            (2, file(AccessMode::Write)),
+            (3, file(AccessMode::Read)),
+            (4, file(AccessMode::ReadWrite)),
+        ];
+        assert_eq!(
+            summarize_open_files(&open_files),
+            "5 open file descriptors (2 read, 1 write, 2 read/write)"
+        );
+        assert_eq!(
+            summarize_open_files(&open_files[..1]),
+            "1 open file descriptor (1 read, 0 write, 0 read/write)"
+        );
+    }
 }
@@ -50,7 +50,6 @@ impl From<std::num::ParseIntError> for Error {
 ///
 /// Example line:
 /// "  578   577 emacs inode.c"
-#[allow(unused)] // TODO: delete this line for Milestone 1
 fn parse_ps_line(line: &str) -> Result<Process, Error> {
     // ps doesn't output a very nice machine-readable output, so we do some wonky things here to
     // deal with variable amounts of whitespace.
@@ -71,8 +70,17 @@ fn parse_ps_line(line: &str) -> Result<Process, Error> {
 /// This function takes a pid and returns a Process struct for the specified process, or None if
 /// the specified pid doesn't exist. An Error is only returned if ps cannot be executed or
 /// produces unexpected output format.
-#[allow(unused)] // TODO: delete this line for Milestone 1
-fn get_process(pid: usize) -> Result<Option<Process>, Error> {
+///
+/// ```
+/// use inspect_fds::ps_utils;
+///
+/// let pid = std::process::id() as usize;
+/// let process = ps_utils::get_process(pid)
+///     .expect("Error running ps")
+///     .expect("Our own process should exist");
+/// assert_eq!(process.pid, pid);
+/// ```
+pub fn get_process(pid: usize) -> Result<Option<Process>, Error> {
     // Run ps to find the specified pid. We use the ? operator to return an Error if executing ps
     // fails, or if it returns non-utf-8 output. (The extra Error traits above are used to
     // automatically convert errors like std::io::Error or std::string::FromUtf8Error into our
@@ -96,7 +104,6 @@ fn get_process(pid: usize) -> Result<Option<Process>, Error> {
 /// This function takes a pid and returns a list of Process structs for processes that have the
 /// specified pid as their parent process. An Error is returned if ps cannot be executed or
 /// produces unexpected output format.
-#[allow(unused)] // TODO: delete this line for Milestone 5
 pub fn get_child_processes(pid: usize) -> Result<Vec<Process>, Error> {
     let ps_output = Command::new("ps")
         .args(&["--ppid", &pid.to_string(), "-o", "pid= ppid= command="])
@@ -108,11 +115,40 @@ pub fn get_child_processes(pid: usize) -> Result<Vec<Process>, Error> {
     Ok(output)
 }
 
+/// This function takes a Process and returns a Process struct for its parent, or None if it has
+/// no parent (i.e. its ppid is 0) or the parent no longer exists. An Error is returned if ps
+/// cannot be executed or produces unexpected output format.
+pub fn get_parent_process(process: &Process) -> Result<Option<Process>, Error> {
+    if process.ppid == 0 {
+        return Ok(None);
+    }
+    get_process(process.ppid)
+}
+
+/// This function returns a list of Process structs for every process owned by the current user.
+/// An Error is returned if ps cannot be executed or produces unexpected output format. Note that
+/// some of the returned processes (including the ps process itself) may have already exited by
+/// the time the caller gets around to inspecting them.
+pub fn get_user_processes() -> Result<Vec<Process>, Error> {
+    let ps_output = Command::new("ps")
+        .args([
+            "-U",
+            getuid().to_string().as_str(),
+            "-o",
+            "pid= ppid= command=",
+        ])
+        .output()?;
+    let mut output = Vec::new();
+    for line in String::from_utf8(ps_output.stdout)?.lines() {
+        output.push(parse_ps_line(line)?);
+    }
+    Ok(output)
+}
+
 /// This function takes a command name (e.g. "sort" or "./multi_pipe_test") and returns the first
 /// matching process's pid, or None if no matching process is found. It returns an Error if there
 /// is an error running pgrep or parsing pgrep's output.
-#[allow(unused)] // TODO: delete this line for Milestone 1
-fn get_pid_by_command_name(name: &str) -> Result<Option<usize>, Error> {
+pub fn get_pid_by_command_name(name: &str) -> Result<Option<usize>, Error> {
     let output = String::from_utf8(
         Command::new("pgrep")
             .args(&["-xU", getuid().to_string().as_str(), name])
@@ -129,7 +165,6 @@ fn get_pid_by_command_name(name: &str) -> Result<Option<usize>, Error> {
 /// command name (e.g. "./subprocess_test") or a PID (e.g. "5612"). This function returns a
 /// Process struct if the specified process was found, None if no matching processes were found, or
 /// Error if an error was encountered in running ps or pgrep.
-#[allow(unused)] // TODO: delete this line for Milestone 1
 pub fn get_target(query: &str) -> Result<Option<Process>, Error> {
     let pid_by_command = get_pid_by_command_name(query)?;
     if pid_by_command.is_some() {
@@ -163,6 +198,40 @@ mod test {
         let _ = subprocess.kill();
     }
 
+    #[test]
+    fn test_get_user_processes() {
+        let mut subprocess = start_c_program("./multi_pipe_test");
+        let processes = get_user_processes()
+            .expect("get_user_processes returned an error, even though ps should be working");
+        assert!(
+            processes.iter().any(|p| p.pid == subprocess.id() as usize),
+            "Expected get_user_processes to include the multi_pipe_test process we started"
+        );
+        let _ = subprocess.kill();
+    }
+
+    #[test]
+    fn test_get_parent_process() {
+        let mut subprocess = start_c_program("./multi_pipe_test");
+        let process = get_process(subprocess.id() as usize)
+            .expect("get_process returned an error, even though ps should be working")
+            .expect("Expected get_process to find the multi_pipe_test process we started");
+        let parent = get_parent_process(&process)
+            .expect("get_parent_process returned an error, even though ps should be working")
+            .expect("Expected multi_pipe_test to have a parent process");
+        assert_eq!(parent.pid, std::process::id() as usize);
+        let _ = subprocess.kill();
+    }
+
+    #[test]
+    fn test_get_parent_process_no_parent() {
+        let process = Process::new(1, 0, String::from("init"));
+        assert!(
+            get_parent_process(&process).unwrap().is_none(),
+            "Expected get_parent_process to return None for a process with ppid 0"
+        );
+    }
+
     #[test]
     fn test_get_target_invalid_command() {
         let found = get_target("asdflksadfasdf")
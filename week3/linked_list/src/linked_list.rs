@@ -1,24 +1,25 @@
 use std::fmt;
+use std::iter::FromIterator;
 use std::option::Option;
 
-pub struct LinkedList {
-    head: Option<Box<Node>>,
+pub struct LinkedList<T> {
+    head: Option<Box<Node<T>>>,
     size: usize,
 }
 
-struct Node {
-    value: u32,
-    next: Option<Box<Node>>,
+struct Node<T> {
+    value: T,
+    next: Option<Box<Node<T>>>,
 }
 
-impl Node {
-    pub fn new(value: u32, next: Option<Box<Node>>) -> Node {
+impl<T> Node<T> {
+    pub fn new(value: T, next: Option<Box<Node<T>>>) -> Node<T> {
         Node {value: value, next: next}
     }
 }
 
-impl LinkedList {
-    pub fn new() -> LinkedList {
+impl<T> LinkedList<T> {
+    pub fn new() -> LinkedList<T> {
         LinkedList {head: None, size: 0}
     }
     
@@ -30,39 +31,168 @@ impl LinkedList {
         self.get_size() == 0
     }
     
-    pub fn push_front(&mut self, value: u32) {
-        let new_node: Box<Node> = Box::new(Node::new(value, self.head.take()));
+    pub fn push_front(&mut self, value: T) {
+        let new_node: Box<Node<T>> = Box::new(Node::new(value, self.head.take()));
         self.head = Some(new_node);
         self.size += 1;
     }
     
-    pub fn pop_front(&mut self) -> Option<u32> {
-        let node: Box<Node> = self.head.take()?;
+    pub fn pop_front(&mut self) -> Option<T> {
+        let node: Box<Node<T>> = self.head.take()?;
         self.head = node.next;
         self.size -= 1;
         Some(node.value)
     }
+
+    pub fn peek_front(&self) -> Option<&T> {
+        Some(&self.head.as_ref()?.value)
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        let mut current: &mut Option<Box<Node<T>>> = &mut self.head;
+        while let Some(node) = current {
+            current = &mut node.next;
+        }
+        *current = Some(Box::new(Node::new(value, None)));
+        self.size += 1;
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let mut current: &mut Option<Box<Node<T>>> = &mut self.head;
+        // Walk forward until current is the link pointing to the last node
+        while current.as_ref()?.next.is_some() {
+            current = &mut current.as_mut().unwrap().next;
+        }
+        let node: Box<Node<T>> = current.take()?;
+        self.size -= 1;
+        Some(node.value)
+    }
+
+    pub fn insert(&mut self, index: usize, value: T) -> Result<(), &'static str> {
+        if index > self.size {
+            return Err("Index out of bounds");
+        }
+        let mut current: &mut Option<Box<Node<T>>> = &mut self.head;
+        for _ in 0..index {
+            current = &mut current.as_mut().unwrap().next;
+        }
+        let new_node: Box<Node<T>> = Box::new(Node::new(value, current.take()));
+        *current = Some(new_node);
+        self.size += 1;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.size {
+            return None;
+        }
+        let mut current: &mut Option<Box<Node<T>>> = &mut self.head;
+        for _ in 0..index {
+            current = &mut current.as_mut()?.next;
+        }
+        let node: Box<Node<T>> = current.take()?;
+        *current = node.next;
+        self.size -= 1;
+        Some(node.value)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut current: &Option<Box<Node<T>>> = &self.head;
+        for _ in 0..index {
+            current = &current.as_ref()?.next;
+        }
+        Some(&current.as_ref()?.value)
+    }
+
+    pub fn reverse(&mut self) {
+        let mut reversed: Option<Box<Node<T>>> = None;
+        let mut current: Option<Box<Node<T>>> = self.head.take();
+        while let Some(mut node) = current {
+            current = node.next.take();
+            node.next = reversed;
+            reversed = Some(node);
+        }
+        self.head = reversed;
+    }
+
+    pub fn contains(&self, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|v| v == value)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {current: &self.head}
+    }
+
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.iter().cloned().collect()
+    }
 }
 
 
-impl fmt::Display for LinkedList {
+impl<T: fmt::Display> fmt::Display for LinkedList<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut current: &Option<Box<Node>> = &self.head;
-        let mut result = String::new();
-        loop {
-            match current {
-                Some(node) => {
-                    result = format!("{} {}", result, node.value);
-                    current = &node.next;
-                },
-                None => break,
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
             }
+            write!(f, "{}", value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LinkedList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LinkedList ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Default for LinkedList<T> {
+    fn default() -> LinkedList<T> {
+        LinkedList::new()
+    }
+}
+
+/// Extending a list appends each item to the back, so that the list ends with the items in the
+/// same order that the iterator produced them.
+impl<T> Extend<T> for LinkedList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        // Walk to the end of the list once, then keep a cursor there so that each value can be
+        // appended in O(1)
+        let mut tail: &mut Option<Box<Node<T>>> = &mut self.head;
+        while let Some(node) = tail {
+            tail = &mut node.next;
+        }
+        for value in iter {
+            let node = tail.insert(Box::new(Node::new(value, None)));
+            tail = &mut node.next;
+            self.size += 1;
         }
-        write!(f, "{}", result)
     }
 }
 
-impl Drop for LinkedList {
+impl<T> FromIterator<T> for LinkedList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> LinkedList<T> {
+        let mut list = LinkedList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> From<Vec<T>> for LinkedList<T> {
+    fn from(vec: Vec<T>) -> LinkedList<T> {
+        vec.into_iter().collect()
+    }
+}
+
+impl<T> Drop for LinkedList<T> {
     fn drop(&mut self) {
         let mut current = self.head.take();
         while let Some(mut node) = current {
@@ -71,5 +201,235 @@ impl Drop for LinkedList {
     }
 }
 
+pub struct IntoIter<T> {
+    list: LinkedList<T>,
+}
 
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
 
+    fn next(&mut self) -> Option<T> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.get_size(), Some(self.list.get_size()))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for LinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { list: self }
+    }
+}
+
+pub struct Iter<'a, T> {
+    current: &'a Option<Box<Node<T>>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.current.as_ref()?;
+        self.current = &node.next;
+        Some(&node.value)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a LinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_string_list() {
+        let mut list: LinkedList<String> = LinkedList::new();
+        list.push_front(String::from("world"));
+        list.push_front(String::from("hello"));
+        assert_eq!(list.get_size(), 2);
+        assert_eq!(list.to_string(), "hello world");
+        assert_eq!(list.pop_front(), Some(String::from("hello")));
+        assert_eq!(list.pop_front(), Some(String::from("world")));
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_i64_list() {
+        let mut list: LinkedList<i64> = LinkedList::new();
+        for i in -2..=2 {
+            list.push_front(i * 1_000_000_000_000);
+        }
+        assert_eq!(list.get_size(), 5);
+        assert_eq!(list.pop_front(), Some(2_000_000_000_000));
+        assert_eq!(list.pop_front(), Some(1_000_000_000_000));
+        assert_eq!(list.get_size(), 3);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut list: LinkedList<u32> = LinkedList::new();
+        for i in (1..=5).rev() {
+            list.push_front(i);
+        }
+        let mut iter = list.into_iter();
+        assert_eq!(iter.len(), 5);
+        iter.next();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.collect::<Vec<u32>>(), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut list: LinkedList<u32> = LinkedList::new();
+        for i in (1..=3).rev() {
+            list.push_front(i);
+        }
+        assert_eq!(list.iter().collect::<Vec<&u32>>(), vec![&1, &2, &3]);
+        // Iterating again should produce the same values, since iter() doesn't consume the list
+        let mut sum = 0;
+        for val in &list {
+            sum += val;
+        }
+        assert_eq!(sum, 6);
+        assert_eq!(list.get_size(), 3);
+    }
+
+    #[test]
+    fn test_push_pop_back() {
+        let mut list: LinkedList<u32> = LinkedList::new();
+        assert_eq!(list.pop_back(), None);
+        list.push_back(2);
+        list.push_front(1);
+        list.push_back(3);
+        list.push_back(4);
+        assert_eq!(list.get_size(), 4);
+        assert_eq!(list.iter().collect::<Vec<&u32>>(), vec![&1, &2, &3, &4]);
+        assert_eq!(list.pop_back(), Some(4));
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.get_size(), 2);
+        assert_eq!(list.to_string(), "1 2");
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut list: LinkedList<u32> = LinkedList::new();
+        assert_eq!(list.get(0), None);
+        for i in (10..=12).rev() {
+            list.push_front(i);
+        }
+        assert_eq!(list.get(0), Some(&10));
+        assert_eq!(list.get(2), Some(&12));
+        assert_eq!(list.get(3), None);
+        assert_eq!(list.get(100), None);
+    }
+
+    #[test]
+    fn test_vec_round_trip() {
+        let values = vec![String::from("a"), String::from("b"), String::from("c")];
+        let list = LinkedList::from(values.clone());
+        assert_eq!(list.get_size(), 3);
+        assert_eq!(list.to_vec(), values);
+
+        let list: LinkedList<u32> = (1..=4).collect();
+        assert_eq!(list.get_size(), 4);
+        assert_eq!(list.to_vec(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut list: LinkedList<u32> = LinkedList::from(vec![1, 2, 3]);
+        list.reverse();
+        assert_eq!(list.get_size(), 3);
+        assert_eq!(list.to_string(), "3 2 1");
+        assert_eq!(list.pop_front(), Some(3));
+
+        let mut empty: LinkedList<u32> = LinkedList::new();
+        empty.reverse();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_contains() {
+        let list: LinkedList<u32> = LinkedList::from(vec![1, 2, 3]);
+        assert!(list.contains(&2));
+        assert!(!list.contains(&4));
+        let empty: LinkedList<u32> = LinkedList::new();
+        assert!(!empty.contains(&1));
+    }
+
+    #[test]
+    fn test_display_and_debug() {
+        let list: LinkedList<u32> = LinkedList::from(vec![1, 2, 3]);
+        assert_eq!(format!("{}", list), "1 2 3");
+        assert_eq!(format!("{:?}", list), "LinkedList [1, 2, 3]");
+        let empty: LinkedList<u32> = LinkedList::new();
+        assert_eq!(format!("{}", empty), "");
+        assert_eq!(format!("{:?}", empty), "LinkedList []");
+    }
+
+    #[test]
+    fn test_peek_front() {
+        let mut list: LinkedList<String> = LinkedList::new();
+        assert_eq!(list.peek_front(), None);
+        list.push_front(String::from("b"));
+        list.push_front(String::from("a"));
+        let peeked = list.peek_front().cloned();
+        assert_eq!(peeked, Some(String::from("a")));
+        assert_eq!(list.get_size(), 2);
+        assert_eq!(list.pop_front(), peeked);
+        assert_eq!(list.get_size(), 1);
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut list: LinkedList<u32> = LinkedList::from(vec![1, 3]);
+        assert_eq!(list.insert(1, 2), Ok(()));
+        assert_eq!(list.insert(0, 0), Ok(()));
+        assert_eq!(list.insert(4, 4), Ok(()));
+        assert!(list.insert(6, 6).is_err());
+        assert_eq!(list.get_size(), 5);
+        assert_eq!(list.to_vec(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut list: LinkedList<u32> = LinkedList::from(vec![1, 2, 3, 4]);
+        assert_eq!(list.remove(0), Some(1));
+        assert_eq!(list.remove(1), Some(3));
+        assert_eq!(list.remove(2), None);
+        assert_eq!(list.get_size(), 2);
+        assert_eq!(list.to_vec(), vec![2, 4]);
+        assert_eq!(list.remove(1), Some(4));
+        assert_eq!(list.to_vec(), vec![2]);
+    }
+
+    #[test]
+    fn test_default_and_extend() {
+        let mut list = LinkedList::default();
+        assert!(list.is_empty());
+        list.extend(vec![1, 2, 3]);
+        assert_eq!(list.to_vec(), vec![1, 2, 3]);
+        list.extend(4..=5);
+        assert_eq!(list.get_size(), 5);
+        assert_eq!(list.to_vec(), vec![1, 2, 3, 4, 5]);
+    }
+}